@@ -1,35 +1,34 @@
 extern crate cocoa;
 
-use cocoa::base::{NSUInteger, nil, ObjCSelector};
+use cocoa::base::{NSUInteger, nil, ObjCObject, ObjCSelector};
+use cocoa::rc::autoreleasepool;
 use cocoa::appkit::{NSApp, NSRect, NSPoint, NSSize,
-					NSAutoreleasePool, NSProcessInfo,
-					NSApplication, NSApplicationActivationPolicyRegular,
+					NSProcessInfo,
+					NSApplicationActivationPolicyRegular,
 					NSWindow, NSTitledWindowMask, NSBackingStoreBuffered,
 					NSString,
 					NSMenu, NSMenuItem};
 
 fn main() {
-	unsafe {
-		let pool = NSAutoreleasePool::new(nil);
-
+	autoreleasepool(|_| unsafe {
 		let app = NSApp();
 		app.setActivationPolicy_(NSApplicationActivationPolicyRegular);
 
 		// create Menu Bar
-		let menubar = NSMenu::new(nil).autorelease();
-		let app_menu_item = NSMenuItem::new(nil).autorelease();
+		let menubar = NSMenu::new().autorelease();
+		let app_menu_item = NSMenuItem::new().autorelease();
 		menubar.addItem_(app_menu_item);
 		app.setMainMenu_(menubar);
 
 		// create Application menu
-		let app_menu = NSMenu::new(nil).autorelease();
-		let quit_prefix = NSString::alloc(nil).init_str("Quit \0");
+		let app_menu = NSMenu::new().autorelease();
+		let quit_prefix = NSString::alloc().init_str("Quit \0");
 		let quit_title = quit_prefix.stringByAppendingString_(
-			NSProcessInfo::processInfo(nil).processName()
+			NSProcessInfo::processInfo().processName()
 		);
 		let quit_action = "terminate:".as_selector();
-		let quit_key = NSString::alloc(nil).init_str("q\0");
-		let quit_item = NSMenuItem::alloc(nil).initWithTitle_action_keyEquivalent_(
+		let quit_key = NSString::alloc().init_str("q\0");
+		let quit_item = NSMenuItem::alloc().initWithTitle_action_keyEquivalent_(
 			quit_title,
 			quit_action,
 			quit_key
@@ -38,7 +37,7 @@ fn main() {
 		app_menu_item.setSubmenu_(app_menu);
 
 		// create Window
-		let window = NSWindow::alloc(nil).initWithContentRect_styleMask_backing_defer_(
+		let window = NSWindow::alloc().initWithContentRect_styleMask_backing_defer_(
 			NSRect::new(NSPoint::new(0., 0.), NSSize::new(200., 200.)),
 			NSTitledWindowMask as NSUInteger,
 			NSBackingStoreBuffered,
@@ -46,11 +45,11 @@ fn main() {
 		).autorelease();
 		window.cascadeTopLeftFromPoint_(NSPoint::new(20., 20.));
 		window.center();
-		let title = NSString::alloc(nil).init_str("Hello World!\0");
+		let title = NSString::alloc().init_str("Hello World!\0");
 		window.setTitle_(title);
 		window.makeKeyAndOrderFront_(nil);
 
 		app.activateIgnoringOtherApps_(true);
 		app.run();
-	}
+	})
 }
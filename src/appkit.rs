@@ -7,68 +7,18 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use base::{ObjCMethodCall, id, SEL, NSInteger, NSUInteger};
+use base::{Encode, ObjCMethodCall, ObjCObject, id, SEL, NSInteger, NSUInteger, encode};
 use libc;
 
-pub type CGFloat = f32;
+pub use foundation::{NSAffineTransform, NSAutoreleasePool, NSDate, NSDefaultRunLoopMode};
+pub use foundation::{NSException, NSObject, NSProcessInfo, NSString};
+pub use geometry::{CGFloat, NSAffineTransformStruct, NSMaxXEdge, NSMaxYEdge, NSMinXEdge};
+pub use geometry::{NSMinYEdge, NSPoint, NSRect, NSRectEdge, NSSize};
 
-#[repr(C)]
-pub struct NSPoint {
-    pub x: f64,
-    pub y: f64,
-}
-
-impl NSPoint {
-    #[inline]
-    pub fn new(x: f64, y: f64) -> NSPoint {
-        NSPoint {
-            x: x,
-            y: y,
-        }
-    }
-}
-
-#[repr(C)]
-pub struct NSSize {
-    pub width: f64,
-    pub height: f64,
-}
-
-impl NSSize {
-    #[inline]
-    pub fn new(width: f64, height: f64) -> NSSize {
-        NSSize {
-            width: width,
-            height: height,
-        }
-    }
-}
-
-#[repr(C)]
-pub struct NSRect {
-    pub origin: NSPoint,
-    pub size: NSSize,
-}
-
-impl NSRect {
-    #[inline]
-    pub fn new(origin: NSPoint, size: NSSize) -> NSRect {
-        NSRect {
-            origin: origin,
-            size: size
-        }
-    }
-}
-
-#[link(name = "AppKit", kind = "framework")]
+#[cfg_attr(not(feature = "mock-runtime"), link(name = "AppKit", kind = "framework"))]
 extern {}
 
-#[link(name = "Foundation", kind = "framework")]
-extern {
-    pub static NSDefaultRunLoopMode: id;
-}
-
-pub unsafe fn NSApp() -> id {
+pub unsafe fn NSApp() -> NSApplication {
     "NSApplication".send("sharedApplication", ())
 }
 
@@ -143,7 +93,8 @@ pub enum NSOpenGLPixelFormatAttribute {
     NSOpenGLPFAVirtualScreenCount       = 128,
 }
 
-#[repr(u64)]
+#[cfg_attr(target_word_size = "32", repr(u32))]
+#[cfg_attr(target_word_size = "64", repr(u64))]
 pub enum NSEventType {
     NSLeftMouseDown         = 1,
     NSLeftMouseUp           = 2,
@@ -176,7 +127,7 @@ pub enum NSEventType {
     NSEventTypeEndGesture   = 20,
 }
 
-#[repr(u64)]
+#[repr(i16)]
 pub enum NSEventSubtype {
     NSWindowExposedEventType            = 0,
     NSApplicationActivatedEventType     = 1,
@@ -186,6 +137,20 @@ pub enum NSEventSubtype {
     NSAWTEventType                      = 16,
 }
 
+// `-[NSEvent type]` returns an `NSUInteger`.
+impl Encode for NSEventType {
+    fn encode(_: Option<NSEventType>) -> &'static str {
+        encode::<NSUInteger>()
+    }
+}
+
+// `-[NSEvent subtype]` returns a `short`.
+impl Encode for NSEventSubtype {
+    fn encode(_: Option<NSEventSubtype>) -> &'static str {
+        encode::<i16>()
+    }
+}
+
 #[repr(u64)]
 pub enum NSEventMask {
     NSLeftMouseDownMask         = 1 << NSLeftMouseDown as uint,
@@ -232,357 +197,349 @@ pub enum NSEventModifierFlags {
     NSDeviceIndependentModifierFlagsMask    = 0xffff0000,
 }
 
-pub trait NSAutoreleasePool {
-    unsafe fn new(_: Self) -> id {
-        "NSAutoreleasePool".send("new", ())
-    }
-
-    unsafe fn autorelease(self) -> Self;
-}
-
-impl NSAutoreleasePool for id {
-    unsafe fn autorelease(self) -> id {
-        self.send("autorelease", ())
-    }
-}
-
-pub trait NSProcessInfo {
-    unsafe fn processInfo(_: Self) -> id {
-        "NSProcessInfo".send("processInfo", ())
-    }
-
-    unsafe fn processName(self) -> id;
-}
-
-impl NSProcessInfo for id {
-    unsafe fn processName(self) -> id {
-        self.send("processName", ())
-    }
-}
-
-pub trait NSApplication {
-    unsafe fn sharedApplication(_: Self) -> id {
+objc_class!(NSEvent: NSObject)
+objc_class!(NSMenu: NSObject)
+objc_class!(NSMenuItem: NSObject)
+objc_class!(NSOpenGLPixelFormat: NSObject)
+objc_class!(NSOpenGLContext: NSObject)
+objc_class!(NSResponder: NSObject)
+objc_class!(NSApplication: NSResponder)
+objc_class!(NSWindow: NSResponder)
+objc_class!(NSScreen: NSObject)
+objc_class!(NSView: NSResponder)
+objc_class!(NSOpenGLView: NSView)
+
+impl NSApplication {
+    pub unsafe fn sharedApplication() -> NSApplication {
         "NSApplication".send("sharedApplication", ())
     }
 
-    unsafe fn setActivationPolicy_(self, policy: NSApplicationActivationPolicy) -> bool;
-    unsafe fn setMainMenu_(self, menu: id);
-    unsafe fn activateIgnoringOtherApps_(self, ignore: bool);
-    unsafe fn run(self);
-    unsafe fn finishLaunching(self);
-    unsafe fn nextEventMatchingMask_untilDate_inMode_dequeue_(self,
-                                                              mask: NSUInteger,
-                                                              expiration: id,
-                                                              in_mode: id,
-                                                              dequeue: bool) -> id;
-    unsafe fn sendEvent_(self, an_event: id);
-}
-
-impl NSApplication for id {
-    unsafe fn setActivationPolicy_(self, policy: NSApplicationActivationPolicy) -> bool {
-        self.send_bool("setActivationPolicy:", policy as NSInteger)
+    pub unsafe fn setActivationPolicy_(&self, policy: NSApplicationActivationPolicy) -> bool {
+        self.as_id().send("setActivationPolicy:", (policy as NSInteger,))
     }
 
-    unsafe fn setMainMenu_(self, menu: id) {
-        self.send_void("setMainMenu:", menu)
+    pub unsafe fn setMainMenu_(&self, menu: NSMenu) {
+        self.as_id().send("setMainMenu:", (menu,))
     }
 
-    unsafe fn activateIgnoringOtherApps_(self, ignore: bool) {
-        self.send_void("activateIgnoringOtherApps:", ignore);
+    pub unsafe fn activateIgnoringOtherApps_(&self, ignore: bool) {
+        self.as_id().send("activateIgnoringOtherApps:", (ignore,))
     }
 
-    unsafe fn run(self) {
-        self.send_void("run", ());
+    pub unsafe fn run(&self) {
+        self.as_id().send("run", ())
     }
 
-    unsafe fn finishLaunching(self) {
-        self.send_void("finishLaunching", ())
+    pub unsafe fn finishLaunching(&self) {
+        self.as_id().send("finishLaunching", ())
     }
 
-    unsafe fn nextEventMatchingMask_untilDate_inMode_dequeue_(self,
-                                                              mask: NSUInteger,
-                                                              expiration: id,
-                                                              in_mode: id,
-                                                              dequeue: bool) -> id {
-        self.send("nextEventMatchingMask:untilDate:inMode:dequeue:",
-                  (mask, expiration, in_mode, dequeue))
+    /// Returns the next matching event, or a `nil` handle if none arrives before `expiration`.
+    pub unsafe fn nextEventMatchingMask_untilDate_inMode_dequeue_(&self,
+                                                                  mask: NSUInteger,
+                                                                  expiration: NSDate,
+                                                                  in_mode: NSString,
+                                                                  dequeue: bool) -> NSEvent {
+        self.as_id().send("nextEventMatchingMask:untilDate:inMode:dequeue:",
+                          (mask, expiration, in_mode, dequeue))
     }
 
-    unsafe fn sendEvent_(self, an_event: id) {
-        self.send_void("sendEvent:", an_event)
+    pub unsafe fn sendEvent_(&self, an_event: NSEvent) {
+        self.as_id().send("sendEvent:", (an_event,))
     }
 }
 
-pub trait NSMenu {
-    unsafe fn new(_: Self) -> id {
+impl NSMenu {
+    pub unsafe fn new() -> NSMenu {
         "NSMenu".send("new", ())
     }
 
-    unsafe fn addItem_(self, menu_item: id);
-}
-
-impl NSMenu for id {
-    unsafe fn addItem_(self, menu_item: id) {
-        self.send_void("addItem:", menu_item)
+    pub unsafe fn addItem_(&self, menu_item: NSMenuItem) {
+        self.as_id().send("addItem:", (menu_item,))
     }
 }
 
-pub trait NSMenuItem {
-    unsafe fn alloc(_: Self) -> id {
+impl NSMenuItem {
+    pub unsafe fn alloc() -> NSMenuItem {
         "NSMenuItem".send("alloc", ())
     }
 
-    unsafe fn new(_: Self) -> id {
+    pub unsafe fn new() -> NSMenuItem {
         "NSMenuItem".send("new", ())
     }
 
-    unsafe fn initWithTitle_action_keyEquivalent_(self, title: id, action: SEL, key: id) -> id;
-    unsafe fn setSubmenu_(self, submenu: id);
-}
-
-impl NSMenuItem for id {
-    unsafe fn initWithTitle_action_keyEquivalent_(self, title: id, action: SEL, key: id) -> id {
-        self.send("initWithTitle:action:keyEquivalent:", (title, action, key))
+    pub unsafe fn initWithTitle_action_keyEquivalent_(&self,
+                                                      title: NSString,
+                                                      action: SEL,
+                                                      key: NSString) -> NSMenuItem {
+        self.as_id().send("initWithTitle:action:keyEquivalent:", (title, action, key))
     }
 
-    unsafe fn setSubmenu_(self, submenu: id) {
-        self.send_void("setSubmenu:", submenu)
+    pub unsafe fn setSubmenu_(&self, submenu: NSMenu) {
+        self.as_id().send("setSubmenu:", (submenu,))
     }
 }
 
-pub trait NSWindow {
-    unsafe fn alloc(_: Self) -> id {
+impl NSWindow {
+    pub unsafe fn alloc() -> NSWindow {
         "NSWindow".send("alloc", ())
     }
 
-    unsafe fn initWithContentRect_styleMask_backing_defer_(self,
-                                                           rect: NSRect,
-                                                           style: NSUInteger,
-                                                           backing: NSBackingStoreType,
-                                                           defer: bool) -> id;
-    unsafe fn cascadeTopLeftFromPoint_(self, top_left: NSPoint) -> NSPoint;
-    unsafe fn setTitle_(self, title: id);
-    unsafe fn makeKeyAndOrderFront_(self, sender: id);
-    unsafe fn center(self);
-    unsafe fn setContentView_(self, view: id);
-    unsafe fn setAcceptsMouseMovedEvents_(self, accept: bool);
-    unsafe fn isVisible(self) -> bool;
-}
+    pub unsafe fn initWithContentRect_styleMask_backing_defer_(&self,
+                                                               rect: NSRect,
+                                                               style: NSUInteger,
+                                                               backing: NSBackingStoreType,
+                                                               defer: bool) -> NSWindow {
+        self.as_id().send("initWithContentRect:styleMask:backing:defer:",
+                          (rect, style, backing as NSUInteger, defer))
+    }
 
-impl NSWindow for id {
-    unsafe fn initWithContentRect_styleMask_backing_defer_(self,
-                                                           rect: NSRect,
-                                                           style: NSUInteger,
-                                                           backing: NSBackingStoreType,
-                                                           defer: bool) -> id {
-        self.send("initWithContentRect:styleMask:backing:defer:",
-                  (rect, style, backing as NSUInteger, defer))
+    pub unsafe fn cascadeTopLeftFromPoint_(&self, top_left: NSPoint) -> NSPoint {
+        self.as_id().send("cascadeTopLeftFromPoint:", (top_left,))
     }
 
-    unsafe fn cascadeTopLeftFromPoint_(self, top_left: NSPoint) -> NSPoint {
-        self.send_point("cascadeTopLeftFromPoint:", top_left)
+    pub unsafe fn setTitle_(&self, title: NSString) {
+        self.as_id().send("setTitle:", (title,))
     }
 
-    unsafe fn setTitle_(self, title: id) {
-        self.send_void("setTitle:", title);
+    pub unsafe fn makeKeyAndOrderFront_(&self, sender: id) {
+        self.as_id().send("makeKeyAndOrderFront:", (sender,))
     }
 
-    unsafe fn makeKeyAndOrderFront_(self, sender: id) {
-        self.send_void("makeKeyAndOrderFront:", sender)
+    pub unsafe fn center(&self) {
+        self.as_id().send("center", ())
     }
 
-    unsafe fn center(self) {
-        self.send_void("center", ())
+    pub unsafe fn setContentView_(&self, view: NSView) {
+        self.as_id().send("setContentView:", (view,))
     }
 
-    unsafe fn setContentView_(self, view: id) {
-        self.send_void("setContentView:", view)
+    pub unsafe fn setAcceptsMouseMovedEvents_(&self, accept: bool) {
+        self.as_id().send("setAcceptsMouseMovedEvents:", (accept,))
     }
 
-    unsafe fn setAcceptsMouseMovedEvents_(self, accept: bool) {
-        self.send_void("setAcceptsMouseMovedEvents:", accept);
+    pub unsafe fn isVisible(&self) -> bool {
+        self.as_id().send("isVisible", ())
     }
 
-    unsafe fn isVisible(self) -> bool {
-        self.send_bool("isVisible", ())
+    /// Returns the screen the window is mostly on, or `NSScreen::nil()` if it is offscreen.
+    pub unsafe fn screen(&self) -> NSScreen {
+        self.as_id().send("screen", ())
     }
-}
 
-pub trait NSString {
-    unsafe fn alloc(_: Self) -> id {
-        "NSString".send("alloc", ())
+    pub unsafe fn backingScaleFactor(&self) -> CGFloat {
+        self.as_id().send("backingScaleFactor", ())
     }
 
-    unsafe fn initWithUTF8String_(self, c_string: *const u8) -> id;
-    unsafe fn stringByAppendingString_(self, other: id) -> id;
-    unsafe fn init_str(self, string: &str) -> Self;
-    unsafe fn UTF8String(self) -> *const libc::c_char;
+    /// Converts a rectangle from the window's base coordinate system to screen coordinates.
+    pub unsafe fn convertRectToScreen_(&self, rect: NSRect) -> NSRect {
+        self.as_id().send("convertRectToScreen:", (rect,))
+    }
+
+    pub unsafe fn convertRectFromScreen_(&self, rect: NSRect) -> NSRect {
+        self.as_id().send("convertRectFromScreen:", (rect,))
+    }
 }
 
-impl NSString for id {
-    unsafe fn initWithUTF8String_(self, c_string: *const u8) -> id {
-        self.send("initWithUTF8String:", c_string as id)
+impl NSScreen {
+    /// Returns the screen containing the key window, or the one with the menu bar if there is
+    /// no key window.
+    pub unsafe fn mainScreen() -> NSScreen {
+        "NSScreen".send("mainScreen", ())
     }
 
-    unsafe fn stringByAppendingString_(self, other: id) -> id {
-        self.send("stringByAppendingString:", other)
+    pub unsafe fn frame(&self) -> NSRect {
+        self.as_id().send("frame", ())
     }
 
-    unsafe fn init_str(self, string: &str) -> id {
-        self.initWithUTF8String_(string.as_ptr())
+    /// Returns the frame without the menu bar and the Dock.
+    pub unsafe fn visibleFrame(&self) -> NSRect {
+        self.as_id().send("visibleFrame", ())
     }
 
-    unsafe fn UTF8String(self) -> *const libc::c_char {
-        self.send_string("UTF8String", ())
+    /// Returns the number of backing pixels per point, e.g. 2.0 on a Retina display.
+    pub unsafe fn backingScaleFactor(&self) -> CGFloat {
+        self.as_id().send("backingScaleFactor", ())
     }
 }
 
-pub trait NSView {
-    unsafe fn alloc(_: Self) -> id {
+impl NSView {
+    pub unsafe fn alloc() -> NSView {
         "NSView".send("alloc", ())
     }
 
-    unsafe fn init(self) -> id;
-    unsafe fn initWithFrame_(self, frameRect: NSRect) -> id;
-    unsafe fn display_(self);
-    unsafe fn setWantsBestResolutionOpenGLSurface_(self, flag: bool);
-    unsafe fn convertPoint_fromView_(self, point: NSPoint, view: id) -> NSPoint;
-}
+    pub unsafe fn init(&self) -> NSView {
+        self.as_id().send("init", ())
+    }
 
-impl NSView for id {
-    unsafe fn init(self) -> id {
-        self.send("init", ())
+    pub unsafe fn initWithFrame_(&self, frameRect: NSRect) -> NSView {
+        self.as_id().send("initWithFrame:", (frameRect,))
     }
 
-    unsafe fn initWithFrame_(self, frameRect: NSRect) -> id {
-        self.send("initWithFrame:", frameRect)
+    pub unsafe fn display_(&self) {
+        self.as_id().send("display", ())
     }
 
-    unsafe fn display_(self) {
-        self.send_void("display", ())
+    pub unsafe fn setWantsBestResolutionOpenGLSurface_(&self, flag: bool) {
+        self.as_id().send("setWantsBestResolutionOpenGLSurface:", (flag,))
     }
 
-    unsafe fn setWantsBestResolutionOpenGLSurface_(self, flag: bool) {
-        self.send_void("setWantsBestResolutionOpenGLSurface:", flag)
+    /// Converts a point from the coordinate system of `view`, or from the window's if `view` is
+    /// `NSView::nil()`.
+    pub unsafe fn convertPoint_fromView_(&self, point: NSPoint, view: NSView) -> NSPoint {
+        self.as_id().send("convertPoint:fromView:", (point, view))
     }
 
-    unsafe fn convertPoint_fromView_(self, point: NSPoint, view: id) -> NSPoint {
-        self.send_point("convertPoint:fromView:", (point, view))
+    /// Converts a point to the coordinate system of `view`, or to the window's if `view` is
+    /// `NSView::nil()`.
+    pub unsafe fn convertPoint_toView_(&self, point: NSPoint, view: NSView) -> NSPoint {
+        self.as_id().send("convertPoint:toView:", (point, view))
     }
-}
 
-pub trait NSOpenGLView {
-    unsafe fn alloc(_: Self) -> id {
-        "NSOpenGLView".send("alloc", ())
+    pub unsafe fn convertRect_fromView_(&self, rect: NSRect, view: NSView) -> NSRect {
+        self.as_id().send("convertRect:fromView:", (rect, view))
     }
 
-    unsafe fn initWithFrame_pixelFormat_(self, frameRect: NSRect, format: id) -> id;
-    unsafe fn display_(self);
-}
+    pub unsafe fn convertRect_toView_(&self, rect: NSRect, view: NSView) -> NSRect {
+        self.as_id().send("convertRect:toView:", (rect, view))
+    }
 
-impl NSOpenGLView for id {
-    unsafe fn initWithFrame_pixelFormat_(self,  frameRect: NSRect, format: id) -> id {
-        self.send("initWithFrame:pixelFormat:", (frameRect, format))
+    /// Converts a rectangle from the view's coordinate system to pixels in its backing store.
+    pub unsafe fn convertRectToBacking_(&self, rect: NSRect) -> NSRect {
+        self.as_id().send("convertRectToBacking:", (rect,))
     }
 
-    unsafe fn display_(self) {
-        self.send_void("display", ())
+    pub unsafe fn convertRectFromBacking_(&self, rect: NSRect) -> NSRect {
+        self.as_id().send("convertRectFromBacking:", (rect,))
     }
 }
 
-pub trait NSOpenGLPixelFormat {
-    unsafe fn alloc(_: Self) -> id {
-        "NSOpenGLPixelFormat".send("alloc", ())
+impl NSOpenGLView {
+    pub unsafe fn alloc() -> NSOpenGLView {
+        "NSOpenGLView".send("alloc", ())
     }
 
-    unsafe fn initWithAttributes_(self, attributes: &[uint]) -> id;
+    pub unsafe fn initWithFrame_pixelFormat_(&self,
+                                             frameRect: NSRect,
+                                             format: NSOpenGLPixelFormat) -> NSOpenGLView {
+        self.as_id().send("initWithFrame:pixelFormat:", (frameRect, format))
+    }
 }
 
-impl NSOpenGLPixelFormat for id {
-    unsafe fn initWithAttributes_(self, attributes: &[uint]) -> id {
-        self.send("initWithAttributes:", attributes)
+impl NSOpenGLPixelFormat {
+    pub unsafe fn alloc() -> NSOpenGLPixelFormat {
+        "NSOpenGLPixelFormat".send("alloc", ())
+    }
+
+    pub unsafe fn initWithAttributes_(&self, attributes: &[uint]) -> NSOpenGLPixelFormat {
+        self.as_id().send("initWithAttributes:", (attributes.as_ptr(),))
     }
 }
 
-pub trait NSOpenGLContext {
-    unsafe fn alloc(_: Self) -> id {
+impl NSOpenGLContext {
+    pub unsafe fn alloc() -> NSOpenGLContext {
         "NSOpenGLContext".send("alloc", ())
     }
 
-    unsafe fn initWithFormat_shareContext_(self, format: id, shareContext: id) -> id;
-    unsafe fn setView_(self, view: id);
-    unsafe fn makeCurrentContext(self);
-    unsafe fn flushBuffer(self);
-}
-
-impl NSOpenGLContext for id {
-    unsafe fn initWithFormat_shareContext_(self, format: id, shareContext: id) -> id {
-        self.send("initWithFormat:shareContext:", (format, shareContext))
+    /// Pass `NSOpenGLContext::nil()` as `shareContext` to create an unshared context.
+    pub unsafe fn initWithFormat_shareContext_(&self,
+                                               format: NSOpenGLPixelFormat,
+                                               shareContext: NSOpenGLContext) -> NSOpenGLContext {
+        self.as_id().send("initWithFormat:shareContext:", (format, shareContext))
     }
 
-    unsafe fn setView_(self, view: id) {
-        self.send_void("setView:", view)
+    pub unsafe fn setView_(&self, view: NSView) {
+        self.as_id().send("setView:", (view,))
     }
 
-    unsafe fn makeCurrentContext(self) {
-        self.send_void("makeCurrentContext", ())
+    pub unsafe fn makeCurrentContext(&self) {
+        self.as_id().send("makeCurrentContext", ())
     }
 
-    unsafe fn flushBuffer(self) {
-        self.send_void("flushBuffer", ())
+    pub unsafe fn flushBuffer(&self) {
+        self.as_id().send("flushBuffer", ())
     }
 }
 
-pub trait NSDate {
-    unsafe fn distantPast(_: Self) -> id {
-        "NSDate".send("distantPast", ())
+impl NSEvent {
+    pub unsafe fn get_type(&self) -> NSEventType {
+        self.as_id().send("type", ())
     }
 
-    unsafe fn distantFuture(_: Self) -> id {
-        "NSDate".send("distantFuture", ())
+    pub unsafe fn get_subtype(&self) -> NSEventSubtype {
+        self.as_id().send("subtype", ())
     }
-}
 
-impl NSDate for id {
-
-}
-
-pub trait NSEvent {
-    unsafe fn get_type(self) -> NSEventType;
-    unsafe fn get_subtype(self) -> NSEventSubtype;
-    unsafe fn locationInWindow(self) -> NSPoint;
-    unsafe fn characters(self) -> id;
-    unsafe fn charactersIgnoringModifiers(self) -> id;
-    unsafe fn keycode(self) -> libc::c_ushort;
-    unsafe fn modifierFlags(self) -> NSUInteger;
-}
+    pub unsafe fn locationInWindow(&self) -> NSPoint {
+        self.as_id().send("locationInWindow", ())
+    }
 
-impl NSEvent for id {
-    unsafe fn get_type(self) -> NSEventType {
-        self.send_event("type", ())
+    pub unsafe fn characters(&self) -> NSString {
+        self.as_id().send("characters", ())
     }
 
-    unsafe fn get_subtype(self) -> NSEventSubtype {
-        self.send_eventSubtype("subtype", ())
+    pub unsafe fn charactersIgnoringModifiers(&self) -> NSString {
+        self.as_id().send("charactersIgnoringModifiers", ())
     }
 
-    unsafe fn locationInWindow(self) -> NSPoint {
-        self.send_point("locationInWindow", ())
+    pub unsafe fn keycode(&self) -> libc::c_ushort {
+        self.as_id().send("keyCode", ())
     }
 
-    unsafe fn characters(self) -> id {
-        self.send("characters", ())
+    pub unsafe fn modifierFlags(&self) -> NSUInteger {
+        self.as_id().send("modifierFlags", ())
     }
+}
+
+#[cfg(all(test, feature = "mock-runtime"))]
+mod test {
+    use base::{NSUInteger, ObjCMethodCall, ObjCObject, SEL, encode, id};
+    use mock::{Message, define_class, record, stub, stub_class};
+    use super::*;
 
-    unsafe fn charactersIgnoringModifiers(self) -> id {
-        self.send("charactersIgnoringModifiers", ())
+    #[test]
+    pub fn test_event_encodings() {
+        assert_eq!(encode::<NSEventType>(), encode::<NSUInteger>());
+        assert_eq!(encode::<NSEventSubtype>(), "s");
     }
 
-    unsafe fn keycode(self) -> libc::c_ushort {
-        self.send_ushort("keyCode", ())
+    extern fn main_screen(cls: id, _: SEL) -> id {
+        unsafe {
+            cls.send("new", ())
+        }
     }
 
-    unsafe fn modifierFlags(self) -> NSUInteger {
-        self.send_NSUInteger("modifierFlags", ())
+    extern fn scale_factor(_: id, _: SEL) -> CGFloat {
+        2.0
+    }
+
+    extern fn rect_to_backing(_: id, _: SEL, rect: NSRect) -> NSRect {
+        NSRect::new(rect.origin * 2.0, rect.size * 2.0)
+    }
+
+    #[test]
+    pub fn test_coordinate_conversion() {
+        stub_class("NSScreen", "NSObject", &[
+            stub("+mainScreen", main_screen as extern fn(id, SEL) -> id),
+            stub("backingScaleFactor", scale_factor as extern fn(id, SEL) -> CGFloat),
+        ]);
+        define_class("NSResponder", "NSObject");
+        let view_class = stub_class("NSView", "NSResponder", &[
+            stub("convertRectToBacking:", rect_to_backing as extern fn(id, SEL, NSRect) -> NSRect),
+        ]);
+
+        unsafe {
+            assert_eq!(NSScreen::mainScreen().backingScaleFactor(), 2.0);
+            let view: NSView = view_class.send("new", ());
+            let rect = NSRect::new(NSPoint::new(1.0, 2.0), NSSize::new(3.0, 4.0));
+            let ((), messages) = record(|| {
+                assert_eq!(view.convertRectToBacking_(rect),
+                           NSRect::new(NSPoint::new(2.0, 4.0), NSSize::new(6.0, 8.0)));
+            });
+            assert_eq!(messages, vec![Message {
+                receiver: view.as_id(),
+                selector: "convertRectToBacking:".to_string(),
+            }]);
+        }
     }
 }
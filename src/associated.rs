@@ -0,0 +1,177 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Attaching objects and Rust values to objects of any class, using the runtime's associated
+//! objects. Each association is identified by a key, which is a static whose type says what is
+//! stored under it:
+//!
+//!     static WINDOW_STATE: AssociationKey<WindowState> = association_key!();
+//!     static DOCUMENT: AssociationKey<id> = association_key!();
+//!
+//!     set_associated_value(window, &WINDOW_STATE, WindowState::new());
+//!     set_associated_object(window, &DOCUMENT, document, Retain);
+//!     let state: &mut WindowState = associated_value(window, &WINDOW_STATE).unwrap();
+//!
+//! Associated values are dropped when they are replaced or removed, or when the object they are
+//! attached to is deallocated.
+
+use base::{ObjCMethodCall, class, id, nil};
+use base::{objc_getAssociatedObject, objc_removeAssociatedObjects, objc_setAssociatedObject};
+use declare::{ClassDecl, rust_state, set_rust_state};
+
+use libc;
+use std::sync::{Once, ONCE_INIT};
+
+/// How an associated object is held by the object it is attached to.
+#[deriving(Clone, PartialEq, Show)]
+#[repr(uint)]
+pub enum AssociationPolicy {
+    /// A weak reference that is not cleared when the associated object is deallocated.
+    Assign = 0,
+    /// A strong reference, set non-atomically.
+    RetainNonatomic = 1,
+    /// A strong reference to a copy of the object, set non-atomically.
+    CopyNonatomic = 3,
+    /// A strong reference.
+    Retain = 769,
+    /// A strong reference to a copy of the object.
+    Copy = 771,
+}
+
+/// A key identifying an association that holds a `T`: an `id` for associated objects, or any
+/// Rust type for associated values. Keys are told apart by their addresses, so each must be a
+/// separate static initialized with `association_key!()`.
+pub struct AssociationKey<T> {
+    #[doc(hidden)]
+    pub unique: u8,
+}
+
+impl<T> AssociationKey<T> {
+    #[inline]
+    fn as_ptr(&'static self) -> *const libc::c_void {
+        self as *const AssociationKey<T> as *const libc::c_void
+    }
+}
+
+/// Attaches `value` to `obj` under `key`, replacing any object attached before. Attaching `nil`
+/// removes the association.
+pub unsafe fn set_associated_object(obj: id,
+                                    key: &'static AssociationKey<id>,
+                                    value: id,
+                                    policy: AssociationPolicy) {
+    objc_setAssociatedObject(obj, key.as_ptr(), value, policy as libc::uintptr_t);
+}
+
+/// Returns the object attached to `obj` under `key`, or `nil` if there is none.
+pub unsafe fn associated_object(obj: id, key: &'static AssociationKey<id>) -> id {
+    objc_getAssociatedObject(obj, key.as_ptr())
+}
+
+/// Attaches a Rust value to `obj` under `key`, dropping any value attached before.
+pub unsafe fn set_associated_value<T:'static>(obj: id,
+                                              key: &'static AssociationKey<T>,
+                                              value: T) {
+    let holder: id = value_holder_class().send("new", ());
+    set_rust_state(holder, value).unwrap();
+    objc_setAssociatedObject(obj, key.as_ptr(), holder, Retain as libc::uintptr_t);
+    let _: () = holder.send("release", ());
+}
+
+/// Returns the Rust value attached to `obj` under `key`, if there is one.
+pub unsafe fn associated_value<'a, T:'static>(obj: id, key: &'static AssociationKey<T>)
+                                              -> Option<&'a mut T> {
+    let holder = objc_getAssociatedObject(obj, key.as_ptr());
+    if holder == nil {
+        None
+    } else {
+        rust_state::<T>(holder).map(|state| &mut *state)
+    }
+}
+
+/// Removes the Rust value attached to `obj` under `key`, dropping it.
+pub unsafe fn remove_associated_value<T:'static>(obj: id, key: &'static AssociationKey<T>) {
+    objc_setAssociatedObject(obj, key.as_ptr(), nil, Retain as libc::uintptr_t);
+}
+
+/// Removes every object and value attached to `obj`, including those attached by other code.
+pub unsafe fn remove_associations(obj: id) {
+    objc_removeAssociatedObjects(obj);
+}
+
+static HOLDER_CLASS_NAME: &'static str = "RustAssociatedValue";
+static HOLDER_CLASS_INIT: Once = ONCE_INIT;
+
+// Associated values are kept in instances of a class that drops its Rust state when deallocated.
+fn value_holder_class() -> id {
+    HOLDER_CLASS_INIT.doit(|| {
+        let mut decl = ClassDecl::new(HOLDER_CLASS_NAME, class("NSObject")).unwrap();
+        decl.add_rust_state().unwrap();
+        decl.register();
+    });
+    class(HOLDER_CLASS_NAME)
+}
+
+#[cfg(test)]
+mod test {
+    use base::{ObjCMethodCall, class, id, nil};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use super::*;
+
+    static OBJECT: AssociationKey<id> = association_key!();
+    static OTHER_OBJECT: AssociationKey<id> = association_key!();
+    static VALUE: AssociationKey<DropCounter> = association_key!();
+
+    struct DropCounter {
+        drops: Rc<Cell<uint>>,
+    }
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    pub fn test_associated_objects() {
+        unsafe {
+            let host: id = class("NSObject").send("new", ());
+            let attached: id = class("NSObject").send("new", ());
+            assert!(associated_object(host, &OBJECT) == nil);
+            set_associated_object(host, &OBJECT, attached, Retain);
+            assert!(associated_object(host, &OBJECT) == attached);
+            assert!(associated_object(host, &OTHER_OBJECT) == nil);
+            set_associated_object(host, &OBJECT, nil, Retain);
+            assert!(associated_object(host, &OBJECT) == nil);
+            let _: () = attached.send("release", ());
+            let _: () = host.send("release", ());
+        }
+    }
+
+    #[test]
+    pub fn test_associated_values() {
+        let drops = Rc::new(Cell::new(0u));
+        unsafe {
+            let host: id = class("NSObject").send("new", ());
+            assert!(associated_value(host, &VALUE).is_none());
+            set_associated_value(host, &VALUE, DropCounter { drops: drops.clone() });
+            assert!(associated_value(host, &VALUE).is_some());
+            set_associated_value(host, &VALUE, DropCounter { drops: drops.clone() });
+            assert_eq!(drops.get(), 1);
+            remove_associated_value(host, &VALUE);
+            assert_eq!(drops.get(), 2);
+            assert!(associated_value(host, &VALUE).is_none());
+
+            // Deallocating the host drops its values.
+            set_associated_value(host, &VALUE, DropCounter { drops: drops.clone() });
+            let _: () = host.send("release", ());
+            assert_eq!(drops.get(), 3);
+        }
+    }
+}
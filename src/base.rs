@@ -7,17 +7,76 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use appkit::{NSRect, NSPoint, NSEventType, NSEventSubtype};
+#[cfg(not(any(feature = "mock-runtime", feature = "gnustep")))]
+use msgsend::{messenger_for_return, super_messenger_for_return};
+#[cfg(not(any(feature = "mock-runtime", feature = "gnustep")))]
+use msgsend::{ReturnKind, ReturnsFloat, ReturnsScalar, ReturnsStruct};
 
-use libc::{c_double, c_long, c_ulong, c_char};
-use libc;
+pub use encoding;
+pub use encoding::{Encode, Encoding, encode};
+
+use encoding::{MethodEncoding, Struct, Union, Void};
 
-pub type Class = libc::intptr_t;
+use exception::{Exception, catch_exception};
+
+use libc;
+use std::c_str::CString;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicUint, Relaxed};
+
+/// An opaque Objective-C object. Objects are only ever handled through an `id`.
+pub enum Object {}
+
+pub type Class = id;
 pub type IMP = extern "C" fn(id, SEL) -> id;
 pub type Ivar = libc::intptr_t;
-pub type SEL = libc::intptr_t;
 #[allow(non_camel_case_types)]
-pub type id = libc::intptr_t;
+pub type id = *mut Object;
+
+/// An opaque Objective-C protocol.
+pub enum Protocol {}
+
+/// An opaque Objective-C method description.
+pub enum Method {}
+
+/// An opaque Objective-C property description.
+pub enum Property {}
+
+/// The receiver of a message sent to the superclass, and the class whose superclass should
+/// handle it.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct objc_super {
+    pub receiver: id,
+    /// The class in which to start looking up the method.
+    pub super_class: Class,
+}
+
+/// A registered method selector.
+#[repr(C)]
+#[deriving(Clone, PartialEq, Show)]
+pub struct SEL {
+    ptr: *const libc::c_void,
+}
+
+impl SEL {
+    /// Wraps the runtime's representation of a selector.
+    #[inline]
+    pub unsafe fn from_ptr(ptr: *const libc::c_void) -> SEL {
+        SEL {
+            ptr: ptr,
+        }
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const libc::c_void {
+        self.ptr
+    }
+}
 
 #[cfg(target_word_size = "32")]
 pub type NSInteger = libc::c_int;
@@ -31,6 +90,7 @@ pub type NSUInteger = libc::c_ulong;
 
 pub static nil: id = 0 as id;
 
+#[cfg_attr(feature = "gnustep", link(name = "objc"))]
 extern {
     pub fn class_addMethod(cls: Class, name: SEL, imp: IMP, types: *const libc::c_char) -> bool;
     pub fn class_addIvar(cls: Class,
@@ -39,420 +99,755 @@ extern {
                          alignment: u8,
                          types: *const libc::c_char)
                          -> bool;
+    pub fn class_addProtocol(cls: Class, protocol: *mut Protocol) -> bool;
+    pub fn class_conformsToProtocol(cls: Class, protocol: *mut Protocol) -> bool;
+    pub fn class_copyIvarList(cls: Class, outCount: *mut libc::c_uint) -> *mut Ivar;
+    pub fn class_copyMethodList(cls: Class, outCount: *mut libc::c_uint) -> *mut *mut Method;
+    pub fn class_copyPropertyList(cls: Class, outCount: *mut libc::c_uint) -> *mut *mut Property;
+    pub fn class_copyProtocolList(cls: Class, outCount: *mut libc::c_uint) -> *mut *mut Protocol;
+    pub fn class_getInstanceMethod(cls: Class, name: SEL) -> *mut Method;
+    pub fn class_getInstanceSize(cls: Class) -> libc::size_t;
+    pub fn class_getInstanceVariable(cls: Class, name: *const libc::c_char) -> Ivar;
+    pub fn class_getMethodImplementation(cls: Class, name: SEL) -> IMP;
+    pub fn class_getName(cls: Class) -> *const libc::c_char;
+    pub fn class_getSuperclass(cls: Class) -> Class;
+    pub fn class_replaceMethod(cls: Class, name: SEL, imp: IMP, types: *const libc::c_char)
+                               -> Option<IMP>;
+    pub fn ivar_getName(ivar: Ivar) -> *const libc::c_char;
+    pub fn ivar_getOffset(ivar: Ivar) -> libc::ptrdiff_t;
+    pub fn ivar_getTypeEncoding(ivar: Ivar) -> *const libc::c_char;
+    pub fn method_exchangeImplementations(m1: *mut Method, m2: *mut Method);
+    pub fn method_getImplementation(method: *mut Method) -> IMP;
+    pub fn method_getName(method: *mut Method) -> SEL;
+    pub fn method_getTypeEncoding(method: *mut Method) -> *const libc::c_char;
+    pub fn method_setImplementation(method: *mut Method, imp: IMP) -> IMP;
     pub fn object_setInstanceVariable(obj: id, name: *const libc::c_char, value: *mut libc::c_void);
     pub fn object_getInstanceVariable(obj: id, name: *const libc::c_char, outValue: *mut *mut libc::c_void);
+    pub fn object_getClass(obj: id) -> Class;
+    pub fn objc_copyClassList(outCount: *mut libc::c_uint) -> *mut Class;
+    pub fn objc_allocateProtocol(name: *const libc::c_char) -> *mut Protocol;
     pub fn objc_allocateClassPair(superclass: Class, name: *const libc::c_char, extraBytes: libc::size_t)
                                   -> Class;
+    pub fn objc_disposeClassPair(cls: Class);
+    pub fn objc_exception_throw(exception: id) -> !;
+    pub fn objc_getAssociatedObject(object: id, key: *const libc::c_void) -> id;
     pub fn objc_getClass(name: *const libc::c_char) -> id;
+    pub fn objc_getProtocol(name: *const libc::c_char) -> *mut Protocol;
     pub fn objc_msgSend(theReceiver: id, theSelector: SEL) -> id;
     pub fn objc_registerClassPair(cls: Class);
+    pub fn objc_registerProtocol(protocol: *mut Protocol);
+    pub fn objc_removeAssociatedObjects(object: id);
+    pub fn objc_setAssociatedObject(object: id,
+                                    key: *const libc::c_void,
+                                    value: id,
+                                    policy: libc::uintptr_t);
+    pub fn objc_retain(obj: id) -> id;
+    pub fn objc_release(obj: id);
+    pub fn objc_autorelease(obj: id) -> id;
+    pub fn objc_autoreleasePoolPush() -> *mut libc::c_void;
+    pub fn objc_autoreleasePoolPop(context: *mut libc::c_void);
+    pub fn objc_initWeak(location: *mut id, obj: id) -> id;
+    pub fn objc_storeWeak(location: *mut id, obj: id) -> id;
+    pub fn objc_loadWeakRetained(location: *mut id) -> id;
+    pub fn objc_copyWeak(to: *mut id, from: *mut id);
+    pub fn objc_destroyWeak(location: *mut id);
+    pub fn property_getAttributes(property: *mut Property) -> *const libc::c_char;
+    pub fn property_getName(property: *mut Property) -> *const libc::c_char;
+    pub fn protocol_addMethodDescription(protocol: *mut Protocol,
+                                         name: SEL,
+                                         types: *const libc::c_char,
+                                         isRequiredMethod: bool,
+                                         isInstanceMethod: bool);
+    pub fn protocol_addProtocol(protocol: *mut Protocol, addition: *mut Protocol);
+    pub fn protocol_conformsToProtocol(protocol: *mut Protocol, other: *mut Protocol) -> bool;
+    pub fn protocol_getName(protocol: *mut Protocol) -> *const libc::c_char;
+    pub fn sel_getName(sel: SEL) -> *const libc::c_char;
     pub fn sel_registerName(name: *const libc::c_char) -> SEL;
 }
 
-/// A convenience method to convert the name of a class to the class object itself.
+#[cfg(any(feature = "mock-runtime", feature = "gnustep"))]
+extern {
+    pub fn objc_msg_lookup(receiver: id, op: SEL) -> IMP;
+    pub fn objc_msg_lookup_super(sup: *const objc_super, op: SEL) -> IMP;
+}
+
+// Process-wide caches of the classes and selectors looked up by name, so that repeated lookups
+// skip both the C string conversion and the runtime's own (locked) tables. Entries are never
+// evicted: selectors are immortal, and classes cannot be unregistered once they are found.
+static mut CLASSES: *mut Mutex<HashMap<String, id>> = 0 as *mut Mutex<HashMap<String, id>>;
+static mut SELECTORS: *mut Mutex<HashMap<String, SEL>> = 0 as *mut Mutex<HashMap<String, SEL>>;
+static CACHES_INIT: Once = ONCE_INIT;
+
+#[inline]
+fn init_caches() {
+    CACHES_INIT.doit(|| {
+        unsafe {
+            CLASSES = mem::transmute(box Mutex::new(HashMap::<String, id>::new()));
+            SELECTORS = mem::transmute(box Mutex::new(HashMap::<String, SEL>::new()));
+        }
+    });
+}
+
+/// A convenience method to convert the name of a class to the class object itself. Returns `nil`
+/// if no such class is registered yet; only found classes are cached.
+///
+/// Every call takes a process-wide lock and hashes `name`, so this is slow in hot code. Where the
+/// name is known at compile time, `class!` caches the class at the call site instead.
 #[inline]
 pub fn class(name: &str) -> id {
-    unsafe {
+    init_caches();
+    let mut classes = unsafe { (*CLASSES).lock() };
+    match classes.find_equiv(&name) {
+        Some(cls) => return *cls,
+        None => {}
+    }
+    let cls = unsafe {
         objc_getClass(name.to_c_str().as_ptr())
+    };
+    if cls != nil {
+        classes.insert(name.to_string(), cls);
     }
+    cls
 }
 
 /// A convenience method to convert the name of a selector to the selector object.
+///
+/// As with `class`, every call takes a process-wide lock and hashes `name`. Where the name is
+/// known at compile time, `sel!` caches the selector at the call site instead.
 #[inline]
 pub fn selector(name: &str) -> SEL {
-    unsafe {
-        sel_registerName(name.to_c_str().as_ptr())
+    init_caches();
+    let mut selectors = unsafe { (*SELECTORS).lock() };
+    match selectors.find_equiv(&name) {
+        Some(sel) => return *sel,
+        None => {}
     }
+    let sel = unsafe {
+        sel_registerName(name.to_c_str().as_ptr())
+    };
+    selectors.insert(name.to_string(), sel);
+    sel
 }
 
-/// A trait that allows syntax like:
-///
-///     let string = "NSString".send("alloc").send("initWithUTF8String:", "Hello world!");
-pub trait ObjCMethodCall {
-    unsafe fn send<S:ObjCSelector,A:ObjCMethodArgs>(self, selector: S, args: A) -> id;
-    unsafe fn send_double<S:ObjCSelector,A:ObjCMethodDoubleArgs>(self, selector: S, args: A)
-                          -> c_double;
-    unsafe fn send_long<S:ObjCSelector,A:ObjCMethodLongArgs>(self, selector: S, args: A) -> c_long;
-    unsafe fn send_void<S:ObjCSelector,A:ObjCMethodVoidArgs>(self, selector: S, args: A);
-    unsafe fn send_bool<S:ObjCSelector,A:ObjCMethodBoolArgs>(self, selector: S, args: A) -> bool;
-    unsafe fn send_point<S:ObjCSelector,A:ObjCMethodPointArgs>(self, selector: S, args: A) -> NSPoint;
-    unsafe fn send_event<S:ObjCSelector,A:ObjCMethodEventArgs>(self, selector: S, args: A) -> NSEventType;
-    unsafe fn send_eventSubtype<S:ObjCSelector,A:ObjCMethodEventSubtypeArgs>(self, selector: S, args: A) -> NSEventSubtype;
-    unsafe fn send_string<S:ObjCSelector,A:ObjCMethodStringArgs>(self, selector: S, args: A) -> *const libc::c_char;
-    unsafe fn send_ushort<S:ObjCSelector,A:ObjCMethodUShortArgs>(self, selector: S, args: A) -> libc::c_ushort;
-    unsafe fn send_NSUInteger<S:ObjCSelector,A:ObjCMethodNSUIntegerArgs>(self, selector: S, args: A) -> NSUInteger;
-
+/// Looks up the protocol named `name`, returning `None` if no such protocol is registered.
+pub fn protocol(name: &str) -> Option<*mut Protocol> {
+    let protocol = unsafe {
+        objc_getProtocol(name.to_c_str().as_ptr())
+    };
+    if protocol.is_null() {
+        None
+    } else {
+        Some(protocol)
+    }
 }
 
-impl ObjCMethodCall for id {
-    #[inline]
-    unsafe fn send<S:ObjCSelector,A:ObjCMethodArgs>(self, selector: S, args: A) -> id {
-        args.send_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_double<S:ObjCSelector,A:ObjCMethodDoubleArgs>(self, selector: S, args: A)
-                          -> c_double {
-        args.send_double_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_long<S:ObjCSelector,A:ObjCMethodLongArgs>(self, selector: S, args: A)
-                        -> c_long {
-        args.send_long_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_void<S:ObjCSelector,A:ObjCMethodVoidArgs>(self, selector: S, args: A) {
-        args.send_void_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_bool<S:ObjCSelector,A:ObjCMethodBoolArgs>(self, selector: S, args: A)
-                        -> bool {
-        args.send_bool_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_point<S:ObjCSelector,A:ObjCMethodPointArgs>(self, selector: S, args: A)
-                        -> NSPoint {
-        args.send_point_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_event<S:ObjCSelector,A:ObjCMethodEventArgs>(self, selector: S, args: A)
-                        -> NSEventType {
-        args.send_event_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_eventSubtype<S:ObjCSelector,A:ObjCMethodEventSubtypeArgs>(self, selector: S, args: A)
-                        -> NSEventSubtype {
-        args.send_eventSubtype_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_string<S:ObjCSelector,A:ObjCMethodStringArgs>(self, selector: S, args: A)
-                        -> *const libc::c_char {
-        args.send_string_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_ushort<S:ObjCSelector,A:ObjCMethodUShortArgs>(self, selector: S, args: A)
-                        -> libc::c_ushort {
-        args.send_ushort_args(self, selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_NSUInteger<S:ObjCSelector,A:ObjCMethodNSUIntegerArgs>(self, selector: S, args: A)
-                        -> NSUInteger {
-        args.send_NSUInteger_args(self, selector.as_selector())
+/// Returns whether `cls` or one of its superclasses adopts `protocol`, or a protocol that
+/// incorporates it.
+pub fn conforms_to_protocol(cls: Class, protocol: *mut Protocol) -> bool {
+    let mut cls = cls;
+    while cls != nil {
+        unsafe {
+            if class_conformsToProtocol(cls, protocol) {
+                return true
+            }
+            cls = class_getSuperclass(cls);
+        }
     }
+    false
 }
 
-/// A convenience implementation that allows methods on class names to be called directly, as in:
-///
-///     "NSString".send("alloc")
-impl<'a> ObjCMethodCall for &'a str {
-    #[inline]
-    unsafe fn send<S:ObjCSelector,A:ObjCMethodArgs>(self, selector: S, args: A) -> id {
-        args.send_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_double<S:ObjCSelector,A:ObjCMethodDoubleArgs>(self, selector: S, args: A)
-                          -> c_double {
-        args.send_double_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_long<S:ObjCSelector,A:ObjCMethodLongArgs>(self, selector: S, args: A)
-                        -> c_long {
-        args.send_long_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_void<S:ObjCSelector,A:ObjCMethodVoidArgs>(self, selector: S, args: A) {
-        args.send_void_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_bool<S:ObjCSelector,A:ObjCMethodBoolArgs>(self, selector: S, args: A)
-                        -> bool {
-        args.send_bool_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_point<S:ObjCSelector,A:ObjCMethodPointArgs>(self, selector: S, args: A)
-                        -> NSPoint {
-        args.send_point_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_event<S:ObjCSelector,A:ObjCMethodEventArgs>(self, selector: S, args: A)
-                        -> NSEventType {
-        args.send_event_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_eventSubtype<S:ObjCSelector,A:ObjCMethodEventSubtypeArgs>(self, selector: S, args: A)
-                        -> NSEventSubtype {
-        args.send_eventSubtype_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_string<S:ObjCSelector,A:ObjCMethodStringArgs>(self, selector: S, args: A)
-                        -> *const libc::c_char {
-        args.send_string_args(class(self), selector.as_selector())
-    }
-    #[inline]
-    unsafe fn send_ushort<S:ObjCSelector,A:ObjCMethodUShortArgs>(self, selector: S, args: A)
-                        -> libc::c_ushort {
-        args.send_ushort_args(class(self), selector.as_selector())
-    }
+/// A per-call-site cache of a selector, used by the `sel!` and `msg_send!` macros so that
+/// after the first send the selector costs a single load.
+pub struct CachedSelector {
+    #[doc(hidden)]
+    pub ptr: AtomicUint,
+}
+
+impl CachedSelector {
     #[inline]
-    unsafe fn send_NSUInteger<S:ObjCSelector,A:ObjCMethodNSUIntegerArgs>(self, selector: S, args: A)
-                        -> NSUInteger {
-        args.send_NSUInteger_args(class(self), selector.as_selector())
+    pub fn get(&'static self, name: &str) -> SEL {
+        let ptr = self.ptr.load(Relaxed);
+        if ptr != 0 {
+            return SEL { ptr: ptr as *const libc::c_void }
+        }
+        // Racing threads register the same selector, so whichever store wins is fine.
+        let sel = selector(name);
+        self.ptr.store(sel.ptr as uint, Relaxed);
+        sel
     }
 }
 
-/// A trait that allows C strings to be used as selectors without having to convert them first.
-pub trait ObjCSelector {
-    fn as_selector(self) -> SEL;
+/// A per-call-site cache of a class, used by the `class!` macro. Lookups that fail are retried
+/// on the next call, since the class may be registered later.
+pub struct CachedClass {
+    #[doc(hidden)]
+    pub ptr: AtomicUint,
 }
 
-impl<'a> ObjCSelector for &'a str {
+impl CachedClass {
     #[inline]
-    fn as_selector(self) -> SEL {
-        // TODO(pcwalton): Cache somehow.
-        unsafe {
-            sel_registerName(self.to_c_str().as_ptr())
+    pub fn get(&'static self, name: &str) -> id {
+        let ptr = self.ptr.load(Relaxed);
+        if ptr != 0 {
+            return ptr as id
         }
+        let cls = class(name);
+        self.ptr.store(cls as uint, Relaxed);
+        cls
     }
 }
 
-impl ObjCSelector for SEL {
+impl Encode for id {
     #[inline]
-    fn as_selector(self) -> SEL {
-        self
+    fn encode(_: Option<id>) -> &'static str {
+        "@"
     }
 }
 
-/// Traits that simulate variadic parameters for convenience when sending messages.
-pub trait ObjCMethodArgs {
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id;
+// Protocols are objects, and can be passed to methods such as `conformsToProtocol:`.
+impl Encode for *mut Protocol {
+    #[inline]
+    fn encode(_: Option<*mut Protocol>) -> &'static str {
+        "@"
+    }
 }
-pub trait ObjCMethodDoubleArgs {
-    unsafe fn send_double_args(self, receiver: id, selector: SEL) -> c_double;
+
+impl Encode for SEL {
+    #[inline]
+    fn encode(_: Option<SEL>) -> &'static str {
+        ":"
+    }
 }
-pub trait ObjCMethodLongArgs {
-    unsafe fn send_long_args(self, receiver: id, selector: SEL) -> c_long;
+
+/// Returns how a value of type `R` is returned, for the purposes of picking a messenger.
+#[cfg(not(any(feature = "mock-runtime", feature = "gnustep")))]
+#[inline]
+fn return_kind<R:Encode>() -> ReturnKind {
+    match encode::<R>().as_bytes()[0] {
+        b'f' | b'd' => ReturnsFloat,
+        b'{' => ReturnsStruct(mem::size_of::<R>()),
+        _ => ReturnsScalar,
+    }
 }
-pub trait ObjCMethodVoidArgs {
-    unsafe fn send_void_args(self, receiver: id, selector: SEL);
+
+/// Returns the function to call to send a message returning an `R`. It must be transmuted to a
+/// function pointer whose signature matches the method, with the receiver and selector first.
+#[cfg(not(any(feature = "mock-runtime", feature = "gnustep")))]
+#[inline]
+unsafe fn message_fn<R:Encode>(_: id, _: SEL) -> unsafe extern "C" fn() {
+    messenger_for_return(return_kind::<R>())
 }
-pub trait ObjCMethodBoolArgs {
-    unsafe fn send_bool_args(self, receiver: id, selector: SEL) -> bool;
+
+// libobjc2 only has `objc_msgSend` on some architectures, and the mock runtime cannot forward
+// arbitrary arguments from one written in Rust, so with either one the method is looked up first
+// and then called directly.
+#[cfg(any(feature = "mock-runtime", feature = "gnustep"))]
+#[inline]
+unsafe fn message_fn<R:Encode>(receiver: id, selector: SEL) -> unsafe extern "C" fn() {
+    looked_up_fn::<R>(receiver, objc_msg_lookup(receiver, selector))
 }
-pub trait ObjCMethodPointArgs {
-    unsafe fn send_point_args(self, receiver: id, selector: SEL) -> NSPoint;
+
+// The runtime's stand-in for messages to nil, and the mock runtime's for unimplemented methods,
+// only clear the integer result register, so they are replaced with a function that returns a
+// zeroed `R` however `R` is returned.
+#[cfg(any(feature = "mock-runtime", feature = "gnustep"))]
+unsafe fn looked_up_fn<R:Encode>(receiver: id, imp: IMP) -> unsafe extern "C" fn() {
+    if receiver == nil || is_unimplemented(imp) {
+        let zeroed: extern "C" fn() -> R = zeroed_result::<R>;
+        mem::transmute(zeroed)
+    } else {
+        mem::transmute(imp)
+    }
 }
-pub trait ObjCMethodEventArgs {
-    unsafe fn send_event_args(self, receiver: id, selector: SEL) -> NSEventType;
+
+#[cfg(any(feature = "mock-runtime", feature = "gnustep"))]
+extern "C" fn zeroed_result<R>() -> R {
+    unsafe {
+        mem::zeroed()
+    }
 }
 
-pub trait ObjCMethodEventSubtypeArgs {
-    unsafe fn send_eventSubtype_args(self, receiver: id, selector: SEL) -> NSEventSubtype;
+#[cfg(feature = "mock-runtime")]
+#[inline]
+fn is_unimplemented(imp: IMP) -> bool {
+    ::mock::is_unimplemented(imp)
 }
 
-pub trait ObjCMethodStringArgs {
-    unsafe fn send_string_args(self, receiver: id, selector: SEL) -> *const libc::c_char;
+#[cfg(all(feature = "gnustep", not(feature = "mock-runtime")))]
+#[inline]
+fn is_unimplemented(_: IMP) -> bool {
+    false
 }
 
-pub trait ObjCMethodUShortArgs {
-    unsafe fn send_ushort_args(self, received: id, selector: SEL) -> libc::c_ushort;
+/// Returns the function to call to send a message returning an `R` to the superclass, and the
+/// value to pass to it in place of the receiver.
+#[cfg(not(any(feature = "mock-runtime", feature = "gnustep")))]
+#[inline]
+unsafe fn super_message_fn<R:Encode>(sup: &objc_super, _: SEL) -> (unsafe extern "C" fn(), id) {
+    (super_messenger_for_return(return_kind::<R>()), sup as *const objc_super as id)
 }
 
-pub trait ObjCMethodNSUIntegerArgs {
-    unsafe fn send_NSUInteger_args(self, received: id, selector: SEL) -> NSUInteger;
+#[cfg(any(feature = "mock-runtime", feature = "gnustep"))]
+#[inline]
+unsafe fn super_message_fn<R:Encode>(sup: &objc_super, selector: SEL)
+                                     -> (unsafe extern "C" fn(), id) {
+    (looked_up_fn::<R>(sup.receiver, objc_msg_lookup_super(sup, selector)), sup.receiver)
 }
 
-impl ObjCMethodArgs for () {
-    #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        invoke_msg_id(receiver, selector)
+/// A trait that allows syntax like:
+///
+///     let string: id = "NSString".send("alloc", ()).send("initWithUTF8String:", (bytes,));
+///
+/// The return type is chosen by the caller, usually through a type annotation on the binding
+/// or the enclosing function's return type.
+pub trait ObjCMethodCall {
+    /// Returns the object messages are sent to.
+    #[doc(hidden)]
+    unsafe fn receiver(self) -> id;
+
+    unsafe fn send<R:Encode,S:ObjCSelector,A:MessageArguments>(self, selector: S, args: A) -> R;
+
+    /// Sends a message that is handled by the implementation in `superclass`, as `[super ...]`
+    /// does:
+    ///
+    ///     extern fn key_down(this: id, sel: SEL, event: id) {
+    ///         unsafe {
+    ///             this.send_super(class("NSView"), sel, (event,))
+    ///         }
+    ///     }
+    ///
+    /// `superclass` must be the superclass of the class that defines the calling method, not of
+    /// the receiver's class, which may be a subclass of it. For class names, the class methods of
+    /// `superclass` are called.
+    unsafe fn send_super<R:Encode,S:ObjCSelector,A:MessageArguments>(self,
+                                                                     superclass: Class,
+                                                                     selector: S,
+                                                                     args: A)
+                                                                     -> R;
+
+    /// Like `send`, but returns any Objective-C exception the method raises as an `Err` instead
+    /// of letting it unwind through Rust.
+    unsafe fn try_send<R:Encode,S:ObjCSelector,A:MessageArguments>(self, selector: S, args: A)
+                                                                   -> Result<R, Exception> {
+        // A verification failure must not unwind through the exception handler, so the message
+        // is verified before entering it.
+        let receiver = self.receiver();
+        let selector = selector.as_selector();
+        verify_receiver::<R, A>(receiver, selector);
+        let mut args = Some(args);
+        catch_exception(|| args.take().unwrap().send_args(receiver, selector))
     }
 }
 
-impl ObjCMethodArgs for id {
+impl ObjCMethodCall for id {
     #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        invoke_msg_id_id(receiver, selector, self)
+    unsafe fn receiver(self) -> id {
+        self
     }
-}
 
-impl ObjCMethodArgs for NSRect {
     #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        invoke_msg_id_NSRect(receiver, selector, &self)
+    unsafe fn send<R:Encode,S:ObjCSelector,A:MessageArguments>(self, selector: S, args: A) -> R {
+        send_message(self, selector.as_selector(), args)
     }
-}
 
-impl ObjCMethodArgs for (id, SEL, id) {
     #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        let (first, second, third) = self;
-        invoke_msg_id_id_SEL_id(receiver, selector, first, second, third)
+    unsafe fn send_super<R:Encode,S:ObjCSelector,A:MessageArguments>(self,
+                                                                     superclass: Class,
+                                                                     selector: S,
+                                                                     args: A)
+                                                                     -> R {
+        send_super_message(self, superclass, selector.as_selector(), args)
     }
 }
 
-impl ObjCMethodArgs for (NSRect, c_ulong, c_ulong, bool) {
+/// A convenience implementation that allows methods on class names to be called directly, as in:
+///
+///     "NSString".send("alloc", ())
+impl<'a> ObjCMethodCall for &'a str {
     #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        let (first, second, third, fourth) = self;
-        invoke_msg_id_NSRect_ulong_ulong_bool(receiver, selector, first, second, third, fourth)
+    unsafe fn receiver(self) -> id {
+        class(self)
     }
-}
 
-impl ObjCMethodArgs for (id, id, id, id, id) {
     #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        let (first, second, third, fourth, fifth) = self;
-        invoke_msg_id_id_id_id_id_id(receiver, selector, first, second, third, fourth, fifth)
+    unsafe fn send<R:Encode,S:ObjCSelector,A:MessageArguments>(self, selector: S, args: A) -> R {
+        send_message(class(self), selector.as_selector(), args)
     }
-}
 
-impl ObjCMethodArgs for (NSRect, id) {
     #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        let (first, second) = self;
-        invoke_msg_id_NSRect_id(receiver, selector, first, second)
+    unsafe fn send_super<R:Encode,S:ObjCSelector,A:MessageArguments>(self,
+                                                                     superclass: Class,
+                                                                     selector: S,
+                                                                     args: A)
+                                                                     -> R {
+        // Class methods are instance methods of the metaclass.
+        let metaclass = object_getClass(superclass);
+        send_super_message(class(self), metaclass, selector.as_selector(), args)
     }
 }
 
-impl<'a> ObjCMethodArgs for &'a [uint] {
-    #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        invoke_msg_id_array(receiver, selector, self)
+#[inline]
+unsafe fn send_message<R:Encode,A:MessageArguments>(receiver: id, selector: SEL, args: A) -> R {
+    verify_receiver::<R, A>(receiver, selector);
+    args.send_args(receiver, selector)
+}
+
+#[inline]
+unsafe fn verify_receiver<R:Encode,A:MessageArguments>(receiver: id, selector: SEL) {
+    if receiver != nil {
+        verify::<R, A>(object_getClass(receiver), selector);
     }
 }
 
-impl ObjCMethodArgs for (id, id) {
-    #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        let (first, second) = self;
-        invoke_msg_id_id_id(receiver, selector, first, second)
+#[inline]
+unsafe fn send_super_message<R:Encode,A:MessageArguments>(receiver: id,
+                                                          superclass: Class,
+                                                          selector: SEL,
+                                                          args: A)
+                                                          -> R {
+    let sup = objc_super {
+        receiver: receiver,
+        super_class: superclass,
+    };
+    if receiver != nil {
+        verify::<R, A>(superclass, selector);
+    }
+    args.send_super_args(&sup, selector)
+}
+
+// With the `verify` feature, every message is checked before it is sent. A message without a
+// method can't be checked, since the receiver may still resolve or forward it.
+#[cfg(feature = "verify")]
+#[inline]
+unsafe fn verify<R:Encode,A:MessageArguments>(cls: Class, selector: SEL) {
+    match verify_method::<R, A>(cls, selector) {
+        Err(err @ SignatureMismatch(..)) => fail!("{}", err),
+        Ok(()) | Err(MethodNotFound(..)) => {}
+    }
+}
+
+#[cfg(not(feature = "verify"))]
+#[inline(always)]
+unsafe fn verify<R:Encode,A:MessageArguments>(_: Class, _: SEL) {
+}
+
+/// The ways a message can fail to match the method it would call.
+#[deriving(Clone, PartialEq)]
+pub enum VerificationError {
+    /// The receiver's class, named first, has no method for the selector named second.
+    MethodNotFound(String, String),
+    /// The method's signature does not match the message. Holds the names of the class and the
+    /// selector, the method's type encoding and the encoding of the message's Rust types.
+    SignatureMismatch(String, String, String, String),
+}
+
+impl fmt::Show for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MethodNotFound(ref class, ref selector) => {
+                write!(f, "{} does not respond to {}", class, selector)
+            }
+            SignatureMismatch(ref class, ref selector, ref expected, ref actual) => {
+                write!(f, "[{} {}] has the signature {}, but was sent as {}",
+                       class, selector, expected, actual)
+            }
+        }
     }
 }
 
-impl ObjCMethodArgs for (NSUInteger, id, id, bool) {
-    #[inline]
-    unsafe fn send_args(self, receiver: id, selector: SEL) -> id {
-        let (first, second, third, fourth) = self;
-        invoke_msg_id_NSUInteger_id_id_bool(receiver, selector, first, second, third, fourth)
+/// Checks that the method `receiver` would call for `selector` takes arguments of the types in
+/// the tuple `A` and returns an `R`, or returns something else if `R` is `()`. Messages to `nil`
+/// always pass. With the `verify` feature, every send is checked like this, and fails if the
+/// signatures do not match. Sends without a method are let through, since `resolveInstanceMethod:`
+/// or forwarding may still handle them.
+pub unsafe fn verify_message<R:Encode,A:MessageArguments>(receiver: id, selector: SEL)
+                                                          -> Result<(), VerificationError> {
+    if receiver == nil {
+        return Ok(())
     }
+    verify_method::<R, A>(object_getClass(receiver), selector)
 }
 
-impl ObjCMethodDoubleArgs for () {
-    #[inline]
-    unsafe fn send_double_args(self, receiver: id, selector: SEL) -> f64 {
-        invoke_msg_double(receiver, selector)
+// Checks a message against the method instances of `cls` have for `selector`.
+unsafe fn verify_method<R:Encode,A:MessageArguments>(cls: Class, selector: SEL)
+                                                     -> Result<(), VerificationError> {
+    let class_name = c_string(class_getName(cls));
+    let selector_name = c_string(sel_getName(selector));
+    let method = class_getInstanceMethod(cls, selector);
+    if method.is_null() {
+        return Err(MethodNotFound(class_name, selector_name))
+    }
+
+    let expected = c_string(method_getTypeEncoding(method));
+    let actual = method_encoding::<R, A>();
+    let matches = match (MethodEncoding::parse(expected.as_slice()),
+                         MethodEncoding::parse(actual.as_slice())) {
+        (Ok(expected), Ok(actual)) => signature_matches(&expected, &actual),
+        _ => false,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(SignatureMismatch(class_name, selector_name, expected, actual))
     }
 }
 
-impl ObjCMethodLongArgs for () {
-    #[inline]
-    unsafe fn send_long_args(self, receiver: id, selector: SEL) -> c_long {
-        invoke_msg_long(receiver, selector)
+/// Returns the type encoding of a method taking the arguments in the tuple `A` and returning an
+/// `R`, e.g. `v@:@` for `method_encoding::<(), (id,)>()`.
+pub fn method_encoding<R:Encode,A:MessageArguments>() -> String {
+    let mut types = encode::<R>().to_string();
+    types.push_str(encode::<id>());
+    types.push_str(encode::<SEL>());
+    for arg in MessageArguments::encodings(None::<A>).iter() {
+        types.push_str(*arg);
     }
+    types
 }
 
-impl ObjCMethodVoidArgs for () {
+fn signature_matches(expected: &MethodEncoding, actual: &MethodEncoding) -> bool {
+    let ret_matches = match (actual.ret.unqualified(), expected.ret.unqualified()) {
+        // Ignoring a result is harmless, unless it is returned in memory.
+        (&Void, &Struct(..)) | (&Void, &Union(..)) => false,
+        (&Void, _) => true,
+        (actual, expected) => actual.is_compatible_with(expected),
+    };
+    ret_matches &&
+        actual.args.len() == expected.args.len() &&
+        actual.args.iter().zip(expected.args.iter()).all(|(a, e)| a.is_compatible_with(e))
+}
+
+/// A trait that allows C strings to be used as selectors without having to convert them first.
+pub trait ObjCSelector {
+    fn as_selector(self) -> SEL;
+}
+
+impl<'a> ObjCSelector for &'a str {
     #[inline]
-    unsafe fn send_void_args(self, receiver: id, selector: SEL) {
-        invoke_msg_void(receiver, selector)
+    fn as_selector(self) -> SEL {
+        selector(self)
     }
 }
 
-impl ObjCMethodVoidArgs for bool {
+impl ObjCSelector for SEL {
     #[inline]
-    unsafe fn send_void_args(self, receiver: id, selector: SEL) {
-        invoke_msg_void_bool(receiver, selector, self)
+    fn as_selector(self) -> SEL {
+        self
     }
 }
 
-impl ObjCMethodVoidArgs for id {
+/// A typed handle to an instance of a particular class, declared with the `objc_class!` macro.
+/// Handles dereference to their superclass's handle, so `NSWindow` has all of `NSResponder`'s
+/// and `NSObject`'s methods, and may be `nil`.
+pub trait ObjCObject {
+    /// The name of the class this handle refers to instances of.
+    fn class_name(_: Option<Self>) -> &'static str;
+
+    /// Wraps an object without checking its class. See `downcast` for a checked conversion.
+    unsafe fn from_id(obj: id) -> Self;
+
+    /// Returns the underlying object.
+    fn as_id(&self) -> id;
+
     #[inline]
-    unsafe fn send_void_args(self, receiver: id, selector: SEL) {
-        invoke_msg_void_id(receiver, selector, self)
+    fn is_nil(&self) -> bool {
+        self.as_id() == nil
     }
-}
 
-impl ObjCMethodBoolArgs for () {
+    /// Returns a handle of a more specific type if the object is an instance of that class or
+    /// one of its subclasses.
     #[inline]
-    unsafe fn send_bool_args(self, receiver: id, selector: SEL) -> bool {
-        invoke_msg_bool(receiver, selector)
+    unsafe fn downcast<T:ObjCObject>(&self) -> Option<T> {
+        downcast(self.as_id())
     }
-}
 
-impl ObjCMethodBoolArgs for c_long {
     #[inline]
-    unsafe fn send_bool_args(self, receiver: id, selector: SEL) -> bool {
-        invoke_msg_bool_long(receiver, selector, self)
+    unsafe fn retain(self) -> Self {
+        ObjCObject::from_id(objc_retain(self.as_id()))
     }
-}
 
-impl ObjCMethodPointArgs for NSPoint {
     #[inline]
-    unsafe fn send_point_args(self, receiver: id, selector: SEL) -> NSPoint {
-        invoke_msg_NSPoint_NSPoint(receiver, selector, self)
+    unsafe fn release(self) {
+        objc_release(self.as_id())
     }
-}
 
-impl ObjCMethodPointArgs for () {
     #[inline]
-    unsafe fn send_point_args(self, receiver: id, selector: SEL) -> NSPoint {
-        invoke_msg_id_NSPoint(receiver, selector)
+    unsafe fn autorelease(self) -> Self {
+        ObjCObject::from_id(objc_autorelease(self.as_id()))
     }
 }
 
-impl ObjCMethodEventArgs for () {
-    #[inline]
-    unsafe fn send_event_args(self, receiver: id, selector: SEL) -> NSEventType {
-        invoke_msg_NSEventType(receiver, selector)
+/// Returns the name of the class that `T` is a handle to.
+#[inline]
+pub fn class_name<T:ObjCObject>() -> &'static str {
+    ObjCObject::class_name(None::<T>)
+}
+
+/// Wraps a raw object in a handle of type `T` if it is an instance of `T`'s class or one of its
+/// subclasses, as reported by `isKindOfClass:`.
+pub unsafe fn downcast<T:ObjCObject>(obj: id) -> Option<T> {
+    if obj == nil {
+        return None
+    }
+    let cls = class(class_name::<T>());
+    if cls == nil {
+        return None
+    }
+    let is_kind: bool = obj.send("isKindOfClass:", (cls,));
+    if is_kind {
+        Some(ObjCObject::from_id(obj))
+    } else {
+        None
     }
 }
 
-impl ObjCMethodPointArgs for (NSPoint, id) {
-    #[inline]
-    unsafe fn send_point_args(self, receiver: id, selector: SEL) -> NSPoint {
-        let (first, second) = self;
-        invoke_msg_NSPoint_NSPoint_id(receiver, selector, first, second)
+/// The ways accessing an instance variable can fail.
+#[deriving(Clone, PartialEq, Show)]
+pub enum IvarError {
+    /// The object's class has no instance variable with this name.
+    NoSuchIvar(String),
+    /// The instance variable's type does not match the Rust type it was accessed as. Holds the
+    /// name of the variable, the encoding of the Rust type and the encoding of the variable.
+    IvarTypeMismatch(String, String, String),
+}
+
+/// A trait for typed access to instance variables, as in:
+///
+///     obj.set_ivar("clicks", clicks + 1);
+///     let clicks: NSUInteger = obj.ivar("clicks").unwrap();
+///
+/// The variable's type encoding is checked against that of the Rust type before every access.
+pub trait ObjCInstanceVariables {
+    /// Returns a pointer to the named instance variable.
+    unsafe fn ivar_ptr<T:Encode>(self, name: &str) -> Result<*mut T, IvarError>;
+    /// Returns the value of the named instance variable.
+    unsafe fn ivar<T:Encode+Copy>(self, name: &str) -> Result<T, IvarError>;
+    /// Sets the named instance variable. Its previous value is overwritten without being dropped.
+    unsafe fn set_ivar<T:Encode>(self, name: &str, value: T) -> Result<(), IvarError>;
+}
+
+impl ObjCInstanceVariables for id {
+    unsafe fn ivar_ptr<T:Encode>(self, name: &str) -> Result<*mut T, IvarError> {
+        let ivar = class_getInstanceVariable(object_getClass(self), name.to_c_str().as_ptr());
+        if ivar == 0 {
+            return Err(NoSuchIvar(name.to_string()))
+        }
+        let actual = c_string(ivar_getTypeEncoding(ivar));
+        let compatible = match Encoding::parse(actual.as_slice()) {
+            Ok(actual) => encoding::encoding::<T>().is_compatible_with(&actual),
+            Err(_) => false,
+        };
+        if !compatible {
+            return Err(IvarTypeMismatch(name.to_string(), encode::<T>().to_string(), actual))
+        }
+        let offset = ivar_getOffset(ivar) as int;
+        Ok((self as *mut u8).offset(offset) as *mut T)
+    }
+
+    unsafe fn ivar<T:Encode+Copy>(self, name: &str) -> Result<T, IvarError> {
+        let ptr: *mut T = try!(self.ivar_ptr(name));
+        Ok(*ptr)
+    }
+
+    unsafe fn set_ivar<T:Encode>(self, name: &str, value: T) -> Result<(), IvarError> {
+        let ptr: *mut T = try!(self.ivar_ptr(name));
+        ptr::write(ptr, value);
+        Ok(())
     }
 }
 
-impl ObjCMethodEventSubtypeArgs for () {
-    #[inline]
-    unsafe fn send_eventSubtype_args(self, receiver: id, selector: SEL) -> NSEventSubtype {
-        invoke_msg_NSEventSubtype(receiver, selector)
+/// Copies a C string owned by the runtime into a Rust string, which is empty if `ptr` is null.
+#[doc(hidden)]
+pub unsafe fn c_string(ptr: *const libc::c_char) -> String {
+    if ptr.is_null() {
+        return String::new()
     }
+    let c_string = CString::new(ptr, false);
+    c_string.as_str().unwrap_or("").to_string()
+}
+
+/// A trait that simulates variadic parameters for method calls. It is implemented for tuples of
+/// up to twelve `Encode` types; a single argument is passed as a one-element tuple, `(arg,)`.
+pub trait MessageArguments {
+    unsafe fn send_args<R:Encode>(self, receiver: id, selector: SEL) -> R;
+
+    unsafe fn send_super_args<R:Encode>(self, sup: &objc_super, selector: SEL) -> R;
+
+    /// Returns the type encodings of the arguments.
+    fn encodings(_: Option<Self>) -> Vec<&'static str>;
 }
 
-impl ObjCMethodStringArgs for () {
+impl MessageArguments for () {
     #[inline]
-    unsafe fn send_string_args(self, receiver: id, selector: SEL) -> *const libc::c_char {
-        invoke_msg_string(receiver, selector)
+    fn encodings(_: Option<()>) -> Vec<&'static str> {
+        Vec::new()
     }
-}
 
-impl ObjCMethodUShortArgs for () {
     #[inline]
-    unsafe fn send_ushort_args(self, receiver: id, selector: SEL) -> libc::c_ushort {
-        invoke_msg_ushort(receiver, selector)
+    unsafe fn send_args<R:Encode>(self, receiver: id, selector: SEL) -> R {
+        let f: unsafe extern "C" fn(id, SEL) -> R =
+            mem::transmute(message_fn::<R>(receiver, selector));
+        f(receiver, selector)
     }
-}
 
-impl ObjCMethodNSUIntegerArgs for () {
     #[inline]
-    unsafe fn send_NSUInteger_args(self, receiver: id, selector: SEL) -> NSUInteger {
-        invoke_msg_NSUInteger(receiver, selector)
+    unsafe fn send_super_args<R:Encode>(self, sup: &objc_super, selector: SEL) -> R {
+        let (f, receiver) = super_message_fn::<R>(sup, selector);
+        let f: unsafe extern "C" fn(id, SEL) -> R = mem::transmute(f);
+        f(receiver, selector)
     }
 }
 
-/// A trait that simulates variadic parameters for method calls.
+macro_rules! message_arguments_impl(
+    ($($a:ident: $t:ident),+) => (
+        impl<$($t:Encode),+> MessageArguments for ($($t,)+) {
+            #[inline]
+            unsafe fn send_args<R:Encode>(self, receiver: id, selector: SEL) -> R {
+                let imp: unsafe extern "C" fn(id, SEL $(, $t)+) -> R =
+                    mem::transmute(message_fn::<R>(receiver, selector));
+                let ($($a,)+) = self;
+                imp(receiver, selector $(, $a)+)
+            }
 
-#[cfg(test)]
+            #[inline]
+            unsafe fn send_super_args<R:Encode>(self, sup: &objc_super, selector: SEL) -> R {
+                let (imp, receiver) = super_message_fn::<R>(sup, selector);
+                let imp: unsafe extern "C" fn(id, SEL $(, $t)+) -> R = mem::transmute(imp);
+                let ($($a,)+) = self;
+                imp(receiver, selector $(, $a)+)
+            }
+
+            #[inline]
+            fn encodings(_: Option<($($t,)+)>) -> Vec<&'static str> {
+                vec![$(encode::<$t>()),+]
+            }
+        }
+    )
+)
+
+message_arguments_impl!(a: A)
+message_arguments_impl!(a: A, b: B)
+message_arguments_impl!(a: A, b: B, c: C)
+message_arguments_impl!(a: A, b: B, c: C, d: D)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K)
+message_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L)
+
+// These talk to the real runtime.
+#[cfg(all(test, target_os = "macos", not(feature = "mock-runtime")))]
 mod test {
     use libc;
+    use test::Bencher;
     use super::*;
 
     #[test]
     pub fn test_nsapp() {
         unsafe {
-            let _nsApp = "NSApplication".send("sharedApplication", ());
+            let _nsApp: id = "NSApplication".send("sharedApplication", ());
         }
     }
 
@@ -475,54 +870,148 @@ mod test {
 
             let mut obj: id = my_object.send("alloc", ());
             obj = obj.send("init", ());
-            obj.send_void("doSomething", ());
+            let result: id = obj.send("doSomething", ());
+            assert!(result == obj);
         }
     }
-}
 
-#[link(name = "msgsend", kind = "static")]
-extern {
-    fn invoke_msg_double(theReceiver: id, theSelector: SEL) -> f64;
-    fn invoke_msg_id(theReceiver: id, theSelector: SEL) -> id;
-    fn invoke_msg_id_id(theReceiver: id, theSelector: SEL, a: id) -> id;
-    fn invoke_msg_id_NSRect(theReceiver: id, theSelector: SEL, a: &NSRect) -> id;
-    fn invoke_msg_id_id_SEL_id(theReceiver: id, theSelector: SEL, a: id, b: SEL, c: id) -> id;
-    fn invoke_msg_id_NSRect_ulong_ulong_bool(theReceiver: id,
-                                             theSelector: SEL,
-                                             a: NSRect,
-                                             b: c_ulong,
-                                             c: c_ulong,
-                                             d: bool) -> id;
-    fn invoke_msg_id_id_id_id_id_id(theReceiver: id,
-                                    theSelector: SEL,
-                                    a: id,
-                                    b: id,
-                                    c: id,
-                                    d: id,
-                                    e: id)
-                                    -> id;
-    fn invoke_msg_id_NSRect_id(theReceiver: id, theSelector: SEL, a: NSRect, b: id) -> id;
-    fn invoke_msg_id_array(theReceiver: id, theSelector: SEL, a: &[uint]) -> id;
-    fn invoke_msg_id_id_id(theReceiver: id, theSelector: SEL, a: id, b: id) -> id;
-    fn invoke_msg_id_NSUInteger_id_id_bool(theReceiver: id,
-                                           theSelector: SEL,
-                                           a: NSUInteger,
-                                           b: id,
-                                           c: id,
-                                           d: bool) -> id;
-    fn invoke_msg_long(theReceiver: id, theSelector: SEL) -> c_long;
-    fn invoke_msg_void(theReceiver: id, theSelector: SEL);
-    fn invoke_msg_void_bool(theReceiver: id, theSelector: SEL, a: bool);
-    fn invoke_msg_void_id(theReceiver: id, theSelector: SEL, a: id);
-    fn invoke_msg_bool(theReceiver: id, theSelector: SEL) -> bool;
-    fn invoke_msg_bool_long(theReceiver: id, theSelector: SEL, a: c_long) -> bool;
-    fn invoke_msg_NSPoint_NSPoint(theReceiver: id, theSelector: SEL, a: NSPoint) -> NSPoint;
-    fn invoke_msg_NSEventType(theReceiver: id, theSelector: SEL) -> NSEventType;
-    fn invoke_msg_id_NSPoint(theReceiver: id, theSelector: SEL) -> NSPoint;
-    fn invoke_msg_NSPoint_NSPoint_id(theReceiver: id, theSelector: SEL, a: NSPoint, b: id) -> NSPoint;
-    fn invoke_msg_NSEventSubtype(theReceiver: id, theSelector: SEL) -> NSEventSubtype;
-    fn invoke_msg_string(theReceiver: id, theSelector: SEL) -> *const libc::c_char;
-    fn invoke_msg_ushort(theReceiver: id, theSelector: SEL) -> libc::c_ushort;
-    fn invoke_msg_NSUInteger(theReceiver: id, theSelector: SEL) -> NSUInteger;
+    #[test]
+    pub fn test_typed_returns() {
+        unsafe {
+            let string: id = "NSString".send("alloc", ());
+            let string: id = string.send("initWithUTF8String:", ("hello\0".as_ptr(),));
+            let length: NSUInteger = string.send("length", ());
+            assert_eq!(length, 5);
+            let c: u16 = string.send("characterAtIndex:", (1 as NSUInteger,));
+            assert_eq!(c, 'e' as u16);
+            let prefixed: bool = string.send("hasPrefix:", (string,));
+            assert!(prefixed);
+            let value: f64 = "NSDate".send("timeIntervalSinceReferenceDate", ());
+            assert!(value > 0.0);
+        }
+    }
+
+    #[test]
+    pub fn test_typed_handles() {
+        use appkit::{NSObject, NSResponder, NSString, NSView};
+
+        unsafe {
+            let string = NSString::alloc().init_str("hello\0");
+            let object: NSObject = *string;
+            assert!(object.as_id() == string.as_id());
+            assert!(object.downcast::<NSString>() == Some(string));
+            assert!(object.downcast::<NSView>().is_none());
+            assert!(downcast::<NSResponder>(nil).is_none());
+            assert!(NSView::nil().is_nil());
+            assert_eq!(class_name::<NSString>(), "NSString");
+            string.release();
+        }
+    }
+
+    #[test]
+    pub fn test_msg_send_macro() {
+        unsafe {
+            assert!(sel!(alloc) == selector("alloc"));
+            assert!(sel!(initWithTitle:action:keyEquivalent:) ==
+                    selector("initWithTitle:action:keyEquivalent:"));
+
+            let string: id = msg_send![class!(NSString), alloc];
+            let string: id = msg_send![string, initWithUTF8String:"hello\0".as_ptr()];
+            let range_start: NSUInteger = 1;
+            let other: id = msg_send![string, substringFromIndex:range_start];
+            let equal: bool = msg_send![other, isEqualToString:string];
+            assert!(!equal);
+            let ch: u16 = msg_send![string, characterAtIndex:0 as NSUInteger];
+            assert_eq!(ch, 'h' as u16);
+        }
+    }
+
+    #[test]
+    pub fn test_caches() {
+        assert!(class("NSObject") == class("NSObject"));
+        assert!(class!(NSObject) == class("NSObject"));
+        assert!(class("NoSuchClassAnywhere") == nil);
+        assert!(selector("description") == "description".as_selector());
+        for _ in range(0u, 2) {
+            assert!(sel!(isEqual:) == selector("isEqual:"));
+        }
+    }
+
+    #[bench]
+    pub fn bench_selector_uncached(b: &mut Bencher) {
+        b.iter(|| unsafe { sel_registerName("isEqual:".to_c_str().as_ptr()) });
+    }
+
+    #[bench]
+    pub fn bench_selector_process_cache(b: &mut Bencher) {
+        b.iter(|| selector("isEqual:"));
+    }
+
+    #[bench]
+    pub fn bench_selector_call_site_cache(b: &mut Bencher) {
+        b.iter(|| sel!(isEqual:));
+    }
+
+    #[bench]
+    pub fn bench_class_uncached(b: &mut Bencher) {
+        b.iter(|| unsafe { objc_getClass("NSString".to_c_str().as_ptr()) });
+    }
+
+    #[bench]
+    pub fn bench_class_process_cache(b: &mut Bencher) {
+        b.iter(|| class("NSString"));
+    }
+
+    #[bench]
+    pub fn bench_class_call_site_cache(b: &mut Bencher) {
+        b.iter(|| class!(NSString));
+    }
 }
 
+#[cfg(all(test, feature = "mock-runtime"))]
+mod mock_test {
+    use declare::ClassDecl;
+    use super::*;
+
+    extern fn add_one(_: id, _: SEL, value: u32) -> u32 {
+        value + 1
+    }
+
+    #[test]
+    pub fn test_verify_message() {
+        let mut decl = ClassDecl::new("RustBaseTestIncrementer", class("NSObject")).unwrap();
+        decl.add_method(selector("increment:"), add_one as extern fn(id, SEL, u32) -> u32).unwrap();
+        let cls = decl.register();
+
+        unsafe {
+            let obj: id = cls.send("new", ());
+            let sel = selector("increment:");
+            assert_eq!(verify_message::<u32, (u32,)>(obj, sel), Ok(()));
+            // A result may be ignored.
+            assert_eq!(verify_message::<(), (u32,)>(obj, sel), Ok(()));
+            assert_eq!(verify_message::<u32, (f64,)>(obj, sel),
+                       Err(SignatureMismatch("RustBaseTestIncrementer".to_string(),
+                                             "increment:".to_string(),
+                                             "I@:I".to_string(),
+                                             "I@:d".to_string())));
+            assert_eq!(verify_message::<u32, ()>(obj, selector("decrement")),
+                       Err(MethodNotFound("RustBaseTestIncrementer".to_string(),
+                                          "decrement".to_string())));
+            assert_eq!(verify_message::<u32, (f64,)>(nil, sel), Ok(()));
+            // Class methods are looked up on the metaclass.
+            assert_eq!(verify_message::<id, ()>(cls, selector("new")), Ok(()));
+        }
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    pub fn test_verify_unimplemented() {
+        // A message without a method isn't checked, since it may be resolved or forwarded.
+        let cls = ClassDecl::new("RustBaseTestUnverified", class("NSObject")).unwrap().register();
+        unsafe {
+            let obj: id = cls.send("new", ());
+            let result: u32 = obj.send("decrement", ());
+            assert_eq!(result, 0);
+        }
+    }
+}
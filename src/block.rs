@@ -0,0 +1,344 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Objective-C blocks. A `ConcreteBlock` wraps a Rust closure in a block that can be passed to
+//! methods that take one:
+//!
+//!     let block = ConcreteBlock::new(|&: obj: id, index: uint, _stop: *mut libc::c_void| {
+//!         println!("{}: {}", index, obj);
+//!     });
+//!     let _: () = array.send("enumerateObjectsUsingBlock:", (&*block,));
+//!
+//! A block that must outlive the current scope, such as a completion handler, should be copied to
+//! the heap with `ConcreteBlock::copy`. Blocks received from Objective-C can be called directly,
+//! or retained with `RcBlock::copy`.
+
+use base::Encode;
+use base::encode;
+
+use libc;
+use std::c_str::CString;
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+
+#[cfg_attr(feature = "gnustep", link(name = "objc"))]
+extern {
+    static _NSConcreteStackBlock: *const libc::c_void;
+
+    fn _Block_copy(block: *const libc::c_void) -> *mut libc::c_void;
+    fn _Block_release(block: *const libc::c_void);
+}
+
+/// The block has copy and dispose helpers in its descriptor.
+pub static BLOCK_HAS_COPY_DISPOSE: libc::c_int = 1 << 25;
+/// The block has a type signature in its descriptor.
+pub static BLOCK_HAS_SIGNATURE: libc::c_int = 1 << 30;
+
+/// A block taking the arguments in the tuple `A` and returning `R`. Blocks are only ever handled
+/// by reference.
+#[repr(C)]
+pub struct Block<A, R> {
+    isa: *const libc::c_void,
+    flags: libc::c_int,
+    _reserved: libc::c_int,
+    invoke: unsafe extern "C" fn(),
+}
+
+impl<A:BlockArguments, R> Block<A, R> {
+    /// Calls the block.
+    #[inline]
+    pub unsafe fn call(&self, args: A) -> R {
+        args.call_block(self)
+    }
+}
+
+impl<'a, A, R> Encode for &'a Block<A, R> {
+    #[inline]
+    fn encode(_: Option<&'a Block<A, R>>) -> &'static str {
+        "@?"
+    }
+}
+
+/// The tuples of arguments that blocks can take.
+pub trait BlockArguments {
+    /// Calls `block` with these arguments.
+    unsafe fn call_block<R>(self, block: &Block<Self, R>) -> R;
+
+    /// Returns the function that calls the closure of a `ConcreteBlock` taking these arguments.
+    fn invoke_fn<R, F:Fn<Self, R>>(_: Option<ConcreteBlock<Self, R, F>>)
+                                   -> unsafe extern "C" fn();
+
+    /// Returns the type encodings of the arguments.
+    fn encodings(_: Option<Self>) -> Vec<&'static str>;
+}
+
+macro_rules! block_arguments_impl(
+    ($invoke:ident; $($a:ident: $t:ident),*) => (
+        unsafe extern "C" fn $invoke<$($t,)* R, F:Fn<($($t,)*), R>>(
+                block: &ConcreteBlock<($($t,)*), R, F>
+                $(, $a: $t)*)
+                -> R {
+            block.context.closure.call(($($a,)*))
+        }
+
+        impl<$($t:Encode),*> BlockArguments for ($($t,)*) {
+            #[inline]
+            unsafe fn call_block<R>(self, block: &Block<($($t,)*), R>) -> R {
+                let invoke: unsafe extern "C" fn(&Block<($($t,)*), R> $(, $t)*) -> R =
+                    mem::transmute(block.invoke);
+                let ($($a,)*) = self;
+                invoke(block $(, $a)*)
+            }
+
+            #[inline]
+            fn invoke_fn<R, F:Fn<($($t,)*), R>>(_: Option<ConcreteBlock<($($t,)*), R, F>>)
+                                                -> unsafe extern "C" fn() {
+                let invoke: unsafe extern "C" fn(&ConcreteBlock<($($t,)*), R, F> $(, $t)*)
+                                                 -> R = $invoke;
+                unsafe {
+                    mem::transmute(invoke)
+                }
+            }
+
+            #[inline]
+            fn encodings(_: Option<($($t,)*)>) -> Vec<&'static str> {
+                vec![$(encode::<$t>()),*]
+            }
+        }
+    );
+)
+
+block_arguments_impl!(invoke_0;)
+block_arguments_impl!(invoke_1; a: A)
+block_arguments_impl!(invoke_2; a: A, b: B)
+block_arguments_impl!(invoke_3; a: A, b: B, c: C)
+block_arguments_impl!(invoke_4; a: A, b: B, c: C, d: D)
+block_arguments_impl!(invoke_5; a: A, b: B, c: C, d: D, e: E)
+block_arguments_impl!(invoke_6; a: A, b: B, c: C, d: D, e: E, f: F_)
+
+#[repr(C)]
+struct BlockDescriptor {
+    _reserved: libc::c_ulong,
+    size: libc::c_ulong,
+    copy_helper: unsafe extern "C" fn(*mut libc::c_void, *const libc::c_void),
+    dispose_helper: unsafe extern "C" fn(*mut libc::c_void),
+    signature: *const libc::c_char,
+}
+
+// Shared by every copy of a `ConcreteBlock`, so that the descriptor outlives them all.
+struct BlockContext<F> {
+    descriptor: BlockDescriptor,
+    #[allow(dead_code)]
+    signature: CString,
+    closure: F,
+}
+
+/// A block that calls a Rust closure. It starts out on the stack, and can be passed by reference
+/// to methods that either call it before returning or copy it.
+#[repr(C)]
+pub struct ConcreteBlock<A, R, F> {
+    base: Block<A, R>,
+    descriptor: *const BlockDescriptor,
+    context: Arc<BlockContext<F>>,
+}
+
+impl<A:BlockArguments, R:Encode, F:Fn<A, R>+Send+Sync> ConcreteBlock<A, R, F> {
+    /// Creates a block that calls `closure`.
+    pub fn new(closure: F) -> ConcreteBlock<A, R, F> {
+        let mut signature = encode::<R>().to_string();
+        signature.push_str("@?");
+        for arg in BlockArguments::encodings(None::<A>).iter() {
+            signature.push_str(*arg);
+        }
+        let signature = signature.to_c_str();
+        let copy: unsafe extern "C" fn(*mut ConcreteBlock<A, R, F>, *const ConcreteBlock<A, R, F>) =
+            copy_helper;
+        let dispose: unsafe extern "C" fn(*mut ConcreteBlock<A, R, F>) = dispose_helper;
+        let context = Arc::new(BlockContext {
+            descriptor: BlockDescriptor {
+                _reserved: 0,
+                size: mem::size_of::<ConcreteBlock<A, R, F>>() as libc::c_ulong,
+                copy_helper: unsafe { mem::transmute(copy) },
+                dispose_helper: unsafe { mem::transmute(dispose) },
+                signature: signature.as_ptr(),
+            },
+            signature: signature,
+            closure: closure,
+        });
+        ConcreteBlock {
+            base: Block {
+                isa: unsafe {
+                    &_NSConcreteStackBlock as *const *const libc::c_void as *const libc::c_void
+                },
+                flags: BLOCK_HAS_COPY_DISPOSE | BLOCK_HAS_SIGNATURE,
+                _reserved: 0,
+                invoke: BlockArguments::invoke_fn(None::<ConcreteBlock<A, R, F>>),
+            },
+            descriptor: &context.descriptor as *const BlockDescriptor,
+            context: context,
+        }
+    }
+
+    /// Copies the block to the heap.
+    pub fn copy(&self) -> RcBlock<A, R> {
+        unsafe {
+            RcBlock::copy(&self.base)
+        }
+    }
+}
+
+impl<A, R, F> Deref<Block<A, R>> for ConcreteBlock<A, R, F> {
+    #[inline]
+    fn deref<'a>(&'a self) -> &'a Block<A, R> {
+        &self.base
+    }
+}
+
+// The runtime copies a block by moving its bytes, and then calls this to fix up the copy.
+unsafe extern "C" fn copy_helper<A, R, F:Send+Sync>(dst: *mut ConcreteBlock<A, R, F>,
+                                                    src: *const ConcreteBlock<A, R, F>) {
+    ptr::write(&mut (*dst).context, (*src).context.clone());
+}
+
+unsafe extern "C" fn dispose_helper<A, R, F:Send+Sync>(block: *mut ConcreteBlock<A, R, F>) {
+    drop(ptr::read(&(*block).context));
+}
+
+/// An owning reference to a heap block, which is retained when cloned and released when dropped.
+pub struct RcBlock<A, R> {
+    ptr: *mut Block<A, R>,
+}
+
+impl<A, R> RcBlock<A, R> {
+    /// Takes ownership of a heap block the caller already owns.
+    #[inline]
+    pub unsafe fn new(ptr: *mut Block<A, R>) -> RcBlock<A, R> {
+        RcBlock {
+            ptr: ptr,
+        }
+    }
+
+    /// Copies `block` to the heap, or retains it if it is already there.
+    #[inline]
+    pub unsafe fn copy(block: *const Block<A, R>) -> RcBlock<A, R> {
+        RcBlock {
+            ptr: _Block_copy(block as *const libc::c_void) as *mut Block<A, R>,
+        }
+    }
+}
+
+impl<A, R> Deref<Block<A, R>> for RcBlock<A, R> {
+    #[inline]
+    fn deref<'a>(&'a self) -> &'a Block<A, R> {
+        unsafe {
+            &*self.ptr
+        }
+    }
+}
+
+impl<A, R> Clone for RcBlock<A, R> {
+    #[inline]
+    fn clone(&self) -> RcBlock<A, R> {
+        unsafe {
+            RcBlock::copy(self.ptr as *const Block<A, R>)
+        }
+    }
+}
+
+impl<A, R> Drop for RcBlock<A, R> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            _Block_release(self.ptr as *const libc::c_void);
+        }
+    }
+}
+
+impl<A, R> Encode for RcBlock<A, R> {
+    #[inline]
+    fn encode(_: Option<RcBlock<A, R>>) -> &'static str {
+        "@?"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libc;
+    use std::c_str::CString;
+    use std::mem;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUint, SeqCst};
+    use super::*;
+    use super::BlockDescriptor;
+
+    struct CountDrops {
+        drops: Arc<AtomicUint>,
+    }
+
+    impl Drop for CountDrops {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, SeqCst);
+        }
+    }
+
+    #[test]
+    pub fn test_call() {
+        let block = ConcreteBlock::new(|&: a: i32, b: f64| -> f64 { a as f64 + b });
+        unsafe {
+            assert_eq!(block.call((1i32, 0.5f64)), 1.5);
+        }
+        let block = ConcreteBlock::new(|&:| -> u8 { 7 });
+        unsafe {
+            assert_eq!(block.call(()), 7);
+        }
+    }
+
+    #[test]
+    pub fn test_layout() {
+        let block = ConcreteBlock::new(|&: x: i32| -> i32 { x });
+        // The descriptor follows the isa pointer, the flags, the reserved word and the invoke
+        // function.
+        let header = mem::size_of::<*const u8>() * 2 + mem::size_of::<libc::c_int>() * 2;
+        unsafe {
+            let start = &*block as *const Block<(i32,), i32> as uint;
+            let descriptor = *((start + header) as *const *const BlockDescriptor);
+            assert_eq!((*descriptor).size as uint, mem::size_of_val(&block));
+            let signature = CString::new((*descriptor).signature, false);
+            assert_eq!(signature.as_str(), Some("i@?i"));
+        }
+        assert!(block.base.flags & BLOCK_HAS_COPY_DISPOSE != 0);
+        assert!(block.base.flags & BLOCK_HAS_SIGNATURE != 0);
+    }
+
+    #[test]
+    pub fn test_copy() {
+        let drops = Arc::new(AtomicUint::new(0));
+        let captured = CountDrops {
+            drops: drops.clone(),
+        };
+        let copied = {
+            let block = ConcreteBlock::new(move |&: x: uint| -> uint {
+                let _ = &captured;
+                x * 2
+            });
+            block.copy()
+        };
+        // The copy keeps the closure alive after the stack block is gone.
+        let other = copied.clone();
+        unsafe {
+            assert_eq!(copied.call((2u,)), 4);
+            drop(copied);
+            assert_eq!(other.call((3u,)), 6);
+        }
+        assert_eq!(drops.load(SeqCst), 0);
+        drop(other);
+        assert_eq!(drops.load(SeqCst), 1);
+    }
+}
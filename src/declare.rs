@@ -0,0 +1,564 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Declaration of new Objective-C classes from Rust, e.g.:
+//!
+//!     extern fn my_view_is_flipped(_: id, _: SEL) -> bool {
+//!         true
+//!     }
+//!
+//!     let mut decl = ClassDecl::new("MyView", class("NSView")).unwrap();
+//!     decl.add_method(selector("isFlipped"),
+//!                     my_view_is_flipped as extern fn(id, SEL) -> bool).unwrap();
+//!     decl.add_ivar::<NSUInteger>("clicks").unwrap();
+//!     decl.add_rust_state().unwrap();
+//!     let my_view = decl.register();
+//!
+//! Instances can then carry arbitrary Rust state, which is dropped when they are deallocated:
+//!
+//!     let view: id = my_view.send("new", ());
+//!     set_rust_state(view, MyViewState::new());
+//!     let state: &mut MyViewState = &mut *rust_state(view).unwrap();
+//!
+//! New protocols are declared in the same way, and can then be adopted by classes:
+//!
+//!     let mut decl = ProtocolDecl::new("MyViewDelegate").unwrap();
+//!     decl.add_protocol("NSObject").unwrap();
+//!     decl.add_method::<(), (id,)>(selector("viewWasClicked:"), true);
+//!     decl.add_method::<bool, (id,)>(selector("viewShouldHighlight:"), false);
+//!     decl.register();
+
+use base::{Class, IMP, IvarError, MessageArguments, ObjCInstanceVariables, Protocol, SEL, id};
+use base::{method_encoding, nil, selector};
+use base::{class_addIvar, class_addMethod, class_addProtocol, class_getMethodImplementation};
+use base::{class_getSuperclass, objc_allocateClassPair, objc_allocateProtocol};
+use base::{objc_disposeClassPair, objc_getProtocol, objc_registerClassPair, objc_registerProtocol};
+use base::{object_getClass, protocol_addMethodDescription, protocol_addProtocol};
+use encoding::{Encode, encode};
+
+use libc;
+use std::any::{Any, AnyMutRefExt};
+use std::mem;
+use std::num::Int;
+use std::ptr;
+
+/// The name of the instance variable that holds the Rust state added by `add_rust_state`.
+pub static RUST_STATE_IVAR: &'static str = "rustState";
+
+/// The ways declaring a class can fail.
+#[deriving(Clone, PartialEq, Show)]
+pub enum DeclError {
+    /// A class with this name is already registered.
+    ClassExists(String),
+    /// The class already has a method for this selector.
+    MethodExists(SEL),
+    /// The class already has an instance variable with this name.
+    IvarExists(String),
+    /// No protocol with this name is registered.
+    NoSuchProtocol(String),
+    /// The class already adopts the protocol with this name.
+    ProtocolAdopted(String),
+    /// A protocol with this name is already registered.
+    ProtocolExists(String),
+}
+
+/// Rust functions that can be used as Objective-C method implementations: `extern fn`s whose
+/// first two arguments are the receiver and the selector, and whose other arguments and return
+/// value all implement `Encode`.
+pub trait MethodImplementation {
+    /// Returns the Objective-C type encoding of the method, derived from the function's signature.
+    fn encoding(&self) -> String;
+    fn imp(self) -> IMP;
+    /// Converts an implementation with this signature back from an `IMP`.
+    unsafe fn from_imp(imp: IMP) -> Self;
+}
+
+macro_rules! method_implementation_impl(
+    ($($t:ident),*) => (
+        impl<R:Encode $(, $t:Encode)*> MethodImplementation for extern "C" fn(id, SEL $(, $t)*) -> R {
+            fn encoding(&self) -> String {
+                let mut types = String::new();
+                types.push_str(encode::<R>());
+                types.push_str(encode::<id>());
+                types.push_str(encode::<SEL>());
+                $(types.push_str(encode::<$t>());)*
+                types
+            }
+
+            fn imp(self) -> IMP {
+                unsafe {
+                    mem::transmute(self)
+                }
+            }
+
+            unsafe fn from_imp(imp: IMP) -> extern "C" fn(id, SEL $(, $t)*) -> R {
+                mem::transmute(imp)
+            }
+        }
+    )
+)
+
+method_implementation_impl!()
+method_implementation_impl!(A)
+method_implementation_impl!(A, B)
+method_implementation_impl!(A, B, C)
+method_implementation_impl!(A, B, C, D)
+method_implementation_impl!(A, B, C, D, E)
+method_implementation_impl!(A, B, C, D, E, F)
+method_implementation_impl!(A, B, C, D, E, F, G)
+method_implementation_impl!(A, B, C, D, E, F, G, H)
+method_implementation_impl!(A, B, C, D, E, F, G, H, I)
+method_implementation_impl!(A, B, C, D, E, F, G, H, I, J)
+method_implementation_impl!(A, B, C, D, E, F, G, H, I, J, K)
+method_implementation_impl!(A, B, C, D, E, F, G, H, I, J, K, L)
+
+/// A class that is being declared. Methods, instance variables and protocols can only be added
+/// before the class is registered; a `ClassDecl` that is dropped without being registered is
+/// disposed of.
+pub struct ClassDecl {
+    cls: Class,
+}
+
+impl ClassDecl {
+    /// Starts declaring a subclass of `superclass` named `name`.
+    pub fn new(name: &str, superclass: Class) -> Result<ClassDecl, DeclError> {
+        let cls = unsafe {
+            objc_allocateClassPair(superclass, name.to_c_str().as_ptr(), 0)
+        };
+        if cls == nil {
+            Err(ClassExists(name.to_string()))
+        } else {
+            Ok(ClassDecl {
+                cls: cls,
+            })
+        }
+    }
+
+    /// Adds an instance method. The method's type encoding is derived from the signature of
+    /// `imp`.
+    pub fn add_method<F:MethodImplementation>(&mut self, sel: SEL, imp: F) -> Result<(), DeclError> {
+        add_method(self.cls, sel, imp)
+    }
+
+    /// Adds a class method. The method's type encoding is derived from the signature of `imp`.
+    pub fn add_class_method<F:MethodImplementation>(&mut self, sel: SEL, imp: F)
+                                                    -> Result<(), DeclError> {
+        let metaclass = unsafe {
+            object_getClass(self.cls)
+        };
+        add_method(metaclass, sel, imp)
+    }
+
+    /// Adds an instance variable of type `T`, with the size and alignment Rust uses for `T`.
+    pub fn add_ivar<T:Encode>(&mut self, name: &str) -> Result<(), DeclError> {
+        // The runtime takes the base two logarithm of the alignment.
+        let alignment = mem::min_align_of::<T>().trailing_zeros() as u8;
+        let added = unsafe {
+            class_addIvar(self.cls,
+                          name.to_c_str().as_ptr(),
+                          mem::size_of::<T>() as libc::size_t,
+                          alignment,
+                          encode::<T>().to_c_str().as_ptr())
+        };
+        if added {
+            Ok(())
+        } else {
+            Err(IvarExists(name.to_string()))
+        }
+    }
+
+    /// Adds an instance variable that can hold a value of any Rust type, set with
+    /// `set_rust_state`, and overrides `dealloc` to drop that value before forwarding to the
+    /// superclass. A class hierarchy can only have one such variable.
+    pub fn add_rust_state(&mut self) -> Result<(), DeclError> {
+        try!(self.add_ivar::<*mut libc::c_void>(RUST_STATE_IVAR));
+        self.add_method(selector("dealloc"), dealloc_rust_state as extern fn(id, SEL))
+    }
+
+    /// Declares that the class conforms to the protocol named `name`.
+    pub fn add_protocol(&mut self, name: &str) -> Result<(), DeclError> {
+        unsafe {
+            let protocol = objc_getProtocol(name.to_c_str().as_ptr());
+            if protocol.is_null() {
+                return Err(NoSuchProtocol(name.to_string()))
+            }
+            if !class_addProtocol(self.cls, protocol) {
+                return Err(ProtocolAdopted(name.to_string()))
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers the class with the runtime and returns it.
+    pub fn register(self) -> Class {
+        let cls = self.cls;
+        unsafe {
+            objc_registerClassPair(cls);
+            mem::forget(self);
+        }
+        cls
+    }
+}
+
+impl Drop for ClassDecl {
+    fn drop(&mut self) {
+        unsafe {
+            objc_disposeClassPair(self.cls);
+        }
+    }
+}
+
+/// A protocol that is being declared. Methods and incorporated protocols can only be added before
+/// the protocol is registered. The runtime cannot dispose of protocols, so a `ProtocolDecl` that
+/// is dropped without being registered is leaked.
+pub struct ProtocolDecl {
+    protocol: *mut Protocol,
+}
+
+impl ProtocolDecl {
+    /// Starts declaring a protocol named `name`.
+    pub fn new(name: &str) -> Result<ProtocolDecl, DeclError> {
+        let protocol = unsafe {
+            objc_allocateProtocol(name.to_c_str().as_ptr())
+        };
+        if protocol.is_null() {
+            Err(ProtocolExists(name.to_string()))
+        } else {
+            Ok(ProtocolDecl {
+                protocol: protocol,
+            })
+        }
+    }
+
+    /// Adds an instance method taking the arguments in the tuple `A` and returning an `R`, which
+    /// conforming classes must implement if `required` is true.
+    pub fn add_method<R:Encode,A:MessageArguments>(&mut self, sel: SEL, required: bool) {
+        self.add_method_description::<R, A>(sel, required, true)
+    }
+
+    /// Adds a class method taking the arguments in the tuple `A` and returning an `R`.
+    pub fn add_class_method<R:Encode,A:MessageArguments>(&mut self, sel: SEL, required: bool) {
+        self.add_method_description::<R, A>(sel, required, false)
+    }
+
+    fn add_method_description<R:Encode,A:MessageArguments>(&mut self,
+                                                           sel: SEL,
+                                                           required: bool,
+                                                           instance: bool) {
+        let types = method_encoding::<R, A>();
+        unsafe {
+            protocol_addMethodDescription(self.protocol,
+                                          sel,
+                                          types.to_c_str().as_ptr(),
+                                          required,
+                                          instance);
+        }
+    }
+
+    /// Declares that the protocol incorporates the protocol named `name`, as `NSWindowDelegate`
+    /// incorporates `NSObject`.
+    pub fn add_protocol(&mut self, name: &str) -> Result<(), DeclError> {
+        unsafe {
+            let protocol = objc_getProtocol(name.to_c_str().as_ptr());
+            if protocol.is_null() {
+                return Err(NoSuchProtocol(name.to_string()))
+            }
+            protocol_addProtocol(self.protocol, protocol);
+        }
+        Ok(())
+    }
+
+    /// Registers the protocol with the runtime and returns it.
+    pub fn register(self) -> *mut Protocol {
+        unsafe {
+            objc_registerProtocol(self.protocol);
+        }
+        self.protocol
+    }
+}
+
+fn add_method<F:MethodImplementation>(cls: Class, sel: SEL, imp: F) -> Result<(), DeclError> {
+    let types = imp.encoding();
+    let added = unsafe {
+        class_addMethod(cls, sel, imp.imp(), types.to_c_str().as_ptr())
+    };
+    if added {
+        Ok(())
+    } else {
+        Err(MethodExists(sel))
+    }
+}
+
+/// Stores `state` in an object whose class was declared with `add_rust_state`, dropping any
+/// state it held before.
+pub unsafe fn set_rust_state<T:'static>(obj: id, state: T) -> Result<(), IvarError> {
+    let ptr: *mut *mut libc::c_void = try!(obj.ivar_ptr(RUST_STATE_IVAR));
+    drop(take_state(ptr));
+    let state = box state as Box<Any>;
+    let state: *mut Box<Any> = mem::transmute(box state);
+    *ptr = state as *mut libc::c_void;
+    Ok(())
+}
+
+/// Returns a pointer to the Rust state of an object whose class was declared with
+/// `add_rust_state`, or `None` if it has none or it is not a `T`. The pointer is valid until the
+/// object is deallocated or its state is replaced.
+pub unsafe fn rust_state<T:'static>(obj: id) -> Option<*mut T> {
+    let ptr: *mut *mut libc::c_void = match obj.ivar_ptr(RUST_STATE_IVAR) {
+        Ok(ptr) => ptr,
+        Err(_) => return None,
+    };
+    let state = *ptr as *mut Box<Any>;
+    if state.is_null() {
+        return None
+    }
+    let state: &mut Any = &mut **state;
+    state.downcast_mut::<T>().map(|state| state as *mut T)
+}
+
+unsafe fn take_state(ptr: *mut *mut libc::c_void) -> Option<Box<Box<Any>>> {
+    let state = *ptr as *mut Box<Any>;
+    *ptr = ptr::null_mut();
+    if state.is_null() {
+        None
+    } else {
+        Some(mem::transmute(state))
+    }
+}
+
+extern fn dealloc_rust_state(this: id, sel: SEL) {
+    unsafe {
+        match this.ivar_ptr::<*mut libc::c_void>(RUST_STATE_IVAR) {
+            Ok(ptr) => drop(take_state(ptr)),
+            Err(_) => {}
+        }
+
+        // Subclasses inherit this implementation and may override it with one that calls it, so
+        // find the class that declared the state, past any overrides, and forward to the first
+        // superclass that does not inherit this implementation.
+        let own = dealloc_rust_state as extern fn(id, SEL);
+        let dealloc_of = |cls: Class| -> extern fn(id, SEL) {
+            mem::transmute(class_getMethodImplementation(cls, sel))
+        };
+        let mut cls = object_getClass(this);
+        while dealloc_of(cls) as *const u8 != own as *const u8 {
+            cls = class_getSuperclass(cls);
+        }
+        while dealloc_of(cls) as *const u8 == own as *const u8 {
+            cls = class_getSuperclass(cls);
+        }
+        dealloc_of(cls)(this, sel)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{IvarTypeMismatch, NoSuchIvar, ObjCInstanceVariables, ObjCMethodCall, SEL};
+    use base::{class, conforms_to_protocol, id, nil, protocol, protocol_conformsToProtocol};
+    use base::selector;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+    use super::*;
+
+    extern fn counter_increment(_: id, _: SEL, by: u32) -> u32 {
+        by + 1
+    }
+
+    extern fn counter_make(_: id, _: SEL) -> id {
+        nil
+    }
+
+    #[test]
+    pub fn test_encoding_from_signature() {
+        let imp = counter_increment as extern fn(id, SEL, u32) -> u32;
+        assert_eq!(imp.encoding().as_slice(), "I@:I");
+        let imp = counter_make as extern fn(id, SEL) -> id;
+        assert_eq!(imp.encoding().as_slice(), "@@:");
+    }
+
+    #[test]
+    pub fn test_declare_class() {
+        let mut decl = ClassDecl::new("RustDeclTestCounter", class("NSObject")).unwrap();
+        decl.add_method(selector("increment:"),
+                        counter_increment as extern fn(id, SEL, u32) -> u32).unwrap();
+        assert_eq!(decl.add_method(selector("increment:"),
+                                   counter_increment as extern fn(id, SEL, u32) -> u32),
+                   Err(MethodExists(selector("increment:"))));
+        decl.add_class_method(selector("make"), counter_make as extern fn(id, SEL) -> id).unwrap();
+        decl.add_ivar::<u64>("count").unwrap();
+        assert_eq!(decl.add_ivar::<u64>("count"), Err(IvarExists("count".to_string())));
+        decl.add_protocol("NSCopying").unwrap();
+        assert_eq!(decl.add_protocol("NSCopying"), Err(ProtocolAdopted("NSCopying".to_string())));
+        assert_eq!(decl.add_protocol("NoSuchProtocolAnywhere"),
+                   Err(NoSuchProtocol("NoSuchProtocolAnywhere".to_string())));
+        let cls = decl.register();
+
+        unsafe {
+            let obj: id = cls.send("new", ());
+            let result: u32 = obj.send("increment:", (41u32,));
+            assert_eq!(result, 42);
+            let made: id = cls.send("make", ());
+            assert!(made == nil);
+            let conforms: bool = obj.send("conformsToProtocol:", (objc_protocol("NSCopying"),));
+            assert!(conforms);
+        }
+
+        assert_eq!(ClassDecl::new("RustDeclTestCounter", class("NSObject")).err(),
+                   Some(ClassExists("RustDeclTestCounter".to_string())));
+    }
+
+    #[test]
+    pub fn test_typed_ivars() {
+        let mut decl = ClassDecl::new("RustDeclTestIvars", class("NSObject")).unwrap();
+        decl.add_ivar::<u16>("small").unwrap();
+        decl.add_ivar::<f64>("value").unwrap();
+        decl.add_ivar::<id>("object").unwrap();
+        let cls = decl.register();
+
+        unsafe {
+            let obj: id = cls.send("new", ());
+            obj.set_ivar("small", 7u16).unwrap();
+            obj.set_ivar("value", 2.5f64).unwrap();
+            obj.set_ivar("object", obj).unwrap();
+            assert_eq!(obj.ivar::<u16>("small"), Ok(7));
+            assert_eq!(obj.ivar::<f64>("value"), Ok(2.5));
+            assert!(obj.ivar::<id>("object") == Ok(obj));
+            assert_eq!(obj.ivar::<f32>("value"),
+                       Err(IvarTypeMismatch("value".to_string(), "f".to_string(), "d".to_string())));
+            assert_eq!(obj.set_ivar("missing", 1u32), Err(NoSuchIvar("missing".to_string())));
+            let _: () = obj.send("release", ());
+        }
+    }
+
+    struct DropCounter {
+        drops: Rc<Cell<uint>>,
+    }
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    pub fn test_rust_state() {
+        let mut decl = ClassDecl::new("RustDeclTestState", class("NSObject")).unwrap();
+        decl.add_rust_state().unwrap();
+        let cls = decl.register();
+        let drops = Rc::new(Cell::new(0u));
+
+        unsafe {
+            let obj: id = cls.send("new", ());
+            assert!(rust_state::<DropCounter>(obj).is_none());
+            set_rust_state(obj, DropCounter { drops: drops.clone() }).unwrap();
+            assert!(rust_state::<DropCounter>(obj).is_some());
+            assert!(rust_state::<String>(obj).is_none());
+            set_rust_state(obj, DropCounter { drops: drops.clone() }).unwrap();
+            assert_eq!(drops.get(), 1);
+            let _: () = obj.send("release", ());
+            assert_eq!(drops.get(), 2);
+        }
+    }
+
+    static OVERRIDE_DEALLOCS: AtomicUint = INIT_ATOMIC_UINT;
+
+    extern fn override_dealloc(this: id, sel: SEL) {
+        OVERRIDE_DEALLOCS.fetch_add(1, SeqCst);
+        unsafe {
+            this.send_super(class("RustDeclTestStateBase"), sel, ())
+        }
+    }
+
+    #[test]
+    pub fn test_rust_state_subclass_dealloc() {
+        let mut decl = ClassDecl::new("RustDeclTestStateBase", class("NSObject")).unwrap();
+        decl.add_rust_state().unwrap();
+        let base = decl.register();
+        let mut decl = ClassDecl::new("RustDeclTestStateOverride", base).unwrap();
+        decl.add_method(selector("dealloc"), override_dealloc as extern fn(id, SEL)).unwrap();
+        let middle = decl.register();
+        // Inherits the override, which used to make the base's `dealloc` call it again.
+        let sub = ClassDecl::new("RustDeclTestStateInherited", middle).unwrap().register();
+        let drops = Rc::new(Cell::new(0u));
+
+        unsafe {
+            let obj: id = sub.send("new", ());
+            set_rust_state(obj, DropCounter { drops: drops.clone() }).unwrap();
+            let _: () = obj.send("release", ());
+        }
+        assert_eq!(drops.get(), 1);
+        assert_eq!(OVERRIDE_DEALLOCS.load(SeqCst), 1);
+    }
+
+    #[test]
+    pub fn test_declare_protocol() {
+        let mut decl = ProtocolDecl::new("RustDeclTestDelegate").unwrap();
+        decl.add_protocol("NSObject").unwrap();
+        assert_eq!(decl.add_protocol("NoSuchProtocolAnywhere"),
+                   Err(NoSuchProtocol("NoSuchProtocolAnywhere".to_string())));
+        decl.add_method::<u32, (u32,)>(selector("increment:"), true);
+        decl.add_class_method::<id, ()>(selector("make"), false);
+        let delegate = decl.register();
+        assert!(protocol("RustDeclTestDelegate") == Some(delegate));
+        assert!(ProtocolDecl::new("RustDeclTestDelegate").is_err());
+        unsafe {
+            assert!(protocol_conformsToProtocol(delegate, protocol("NSObject").unwrap()));
+        }
+
+        let mut decl = ClassDecl::new("RustDeclTestDelegateImpl", class("NSObject")).unwrap();
+        decl.add_method(selector("increment:"),
+                        counter_increment as extern fn(id, SEL, u32) -> u32).unwrap();
+        decl.add_protocol("RustDeclTestDelegate").unwrap();
+        let cls = decl.register();
+        assert!(conforms_to_protocol(cls, delegate));
+        assert!(!conforms_to_protocol(class("NSObject"), delegate));
+
+        unsafe {
+            let obj: id = cls.send("new", ());
+            let conforms: bool = obj.send("conformsToProtocol:", (delegate,));
+            assert!(conforms);
+            let _: () = obj.send("release", ());
+        }
+    }
+
+    extern fn doubled_increment(this: id, sel: SEL, by: u32) -> u32 {
+        unsafe {
+            let result: u32 = this.send_super(class("RustDeclTestSuperBase"), sel, (by,));
+            result * 2
+        }
+    }
+
+    #[test]
+    pub fn test_send_super() {
+        let mut decl = ClassDecl::new("RustDeclTestSuperBase", class("NSObject")).unwrap();
+        decl.add_method(selector("increment:"),
+                        counter_increment as extern fn(id, SEL, u32) -> u32).unwrap();
+        let base = decl.register();
+        let mut decl = ClassDecl::new("RustDeclTestSuperMiddle", base).unwrap();
+        decl.add_method(selector("increment:"),
+                        doubled_increment as extern fn(id, SEL, u32) -> u32).unwrap();
+        let middle = decl.register();
+        // A subclass that does not override the method still reaches the base implementation.
+        let sub = ClassDecl::new("RustDeclTestSuperSub", middle).unwrap().register();
+
+        unsafe {
+            let obj: id = sub.send("new", ());
+            let result: u32 = obj.send("increment:", (20u32,));
+            assert_eq!(result, 42);
+            let result: u32 = obj.send_super(base, "increment:", (20u32,));
+            assert_eq!(result, 21);
+            let _: () = obj.send("release", ());
+        }
+    }
+
+    fn objc_protocol(name: &str) -> id {
+        protocol(name).unwrap() as id
+    }
+}
@@ -0,0 +1,794 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Objective-C type encodings, as produced by `@encode` and used by `class_addMethod`,
+//! `class_addIvar` and `method_getTypeEncoding`.
+//!
+//! This module is reexported as `base::encoding`; it has no dependency on the runtime.
+
+use libc;
+use std::fmt;
+
+/// Types that have an Objective-C type encoding, and so can be passed to and returned from
+/// methods.
+pub trait Encode {
+    /// Returns the type encoding of `Self`, e.g. `"@"` for `id`. The argument is never inspected;
+    /// use the `encode` function instead of calling this directly.
+    fn encode(_: Option<Self>) -> &'static str;
+}
+
+/// Returns the Objective-C type encoding of `T`.
+#[inline]
+pub fn encode<T:Encode>() -> &'static str {
+    Encode::encode(None::<T>)
+}
+
+/// Returns the parsed Objective-C type encoding of `T`.
+pub fn encoding<T:Encode>() -> Encoding {
+    match Encoding::parse(encode::<T>()) {
+        Ok(encoding) => encoding,
+        Err(err) => fail!("invalid encoding {} for a Rust type: {}", encode::<T>(), err),
+    }
+}
+
+macro_rules! encode_impl(
+    ($t:ty, $code:expr) => (
+        impl Encode for $t {
+            #[inline]
+            fn encode(_: Option<$t>) -> &'static str {
+                $code
+            }
+        }
+    )
+)
+
+encode_impl!((), "v")
+encode_impl!(i8, "c")
+encode_impl!(u8, "C")
+encode_impl!(i16, "s")
+encode_impl!(u16, "S")
+encode_impl!(i32, "i")
+encode_impl!(u32, "I")
+encode_impl!(i64, "q")
+encode_impl!(u64, "Q")
+encode_impl!(f32, "f")
+encode_impl!(f64, "d")
+encode_impl!(*const libc::c_char, "r*")
+encode_impl!(*mut libc::c_char, "*")
+encode_impl!(*const u8, "r*")
+encode_impl!(*mut u8, "*")
+encode_impl!(*const libc::c_void, "r^v")
+encode_impl!(*mut libc::c_void, "^v")
+
+#[cfg(target_word_size = "32")]
+encode_impl!(int, "i")
+#[cfg(target_word_size = "32")]
+encode_impl!(uint, "I")
+#[cfg(target_word_size = "32")]
+encode_impl!(*const uint, "r^I")
+
+#[cfg(target_word_size = "64")]
+encode_impl!(int, "q")
+#[cfg(target_word_size = "64")]
+encode_impl!(uint, "Q")
+#[cfg(target_word_size = "64")]
+encode_impl!(*const uint, "r^Q")
+
+// On Apple's runtime `BOOL` is a `signed char` everywhere except on AArch64, where it is a C99
+// `bool`. GNUstep makes it an `unsigned char`.
+#[cfg(all(not(target_arch = "aarch64"), not(feature = "gnustep")))]
+encode_impl!(bool, "c")
+#[cfg(all(target_arch = "aarch64", not(feature = "gnustep")))]
+encode_impl!(bool, "B")
+#[cfg(feature = "gnustep")]
+encode_impl!(bool, "C")
+
+/// A type qualifier, as found in method encodings of protocols and distributed objects.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Qualifier {
+    /// `r`
+    Const,
+    /// `n`
+    In,
+    /// `N`
+    InOut,
+    /// `o`
+    Out,
+    /// `O`
+    Bycopy,
+    /// `R`
+    Byref,
+    /// `V`
+    Oneway,
+}
+
+impl Qualifier {
+    fn from_code(code: u8) -> Option<Qualifier> {
+        match code {
+            b'r' => Some(Const),
+            b'n' => Some(In),
+            b'N' => Some(InOut),
+            b'o' => Some(Out),
+            b'O' => Some(Bycopy),
+            b'R' => Some(Byref),
+            b'V' => Some(Oneway),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> char {
+        match *self {
+            Const => 'r',
+            In => 'n',
+            InOut => 'N',
+            Out => 'o',
+            Bycopy => 'O',
+            Byref => 'R',
+            Oneway => 'V',
+        }
+    }
+}
+
+/// A parsed Objective-C type encoding.
+///
+/// Formatting an `Encoding` with `{}` produces the encoding string again; `describe` produces a
+/// C-like description for humans.
+#[deriving(Clone, PartialEq)]
+pub enum Encoding {
+    /// `c`
+    Char,
+    /// `C`
+    UnsignedChar,
+    /// `s`
+    Short,
+    /// `S`
+    UnsignedShort,
+    /// `i`
+    Int,
+    /// `I`
+    UnsignedInt,
+    /// `l`, which is always 32 bits wide in encodings.
+    Long,
+    /// `L`
+    UnsignedLong,
+    /// `q`
+    LongLong,
+    /// `Q`
+    UnsignedLongLong,
+    /// `f`
+    Float,
+    /// `d`
+    Double,
+    /// `D`
+    LongDouble,
+    /// `B`, a C99 `bool`.
+    Bool,
+    /// `v`
+    Void,
+    /// `*`, a `char *`.
+    CString,
+    /// `@`, or `@"ClassName"` when the class is known.
+    Id(Option<String>),
+    /// `@?`
+    Block,
+    /// `#`
+    Class,
+    /// `:`
+    Sel,
+    /// `?`, an unknown type such as a function pointer's pointee.
+    Unknown,
+    /// `^type`
+    Pointer(Box<Encoding>),
+    /// `[count type]`
+    Array(uint, Box<Encoding>),
+    /// `{name=fields}`, or `{name}` when the fields are omitted.
+    Struct(String, Option<Vec<Encoding>>),
+    /// `(name=fields)`, or `(name)` when the fields are omitted.
+    Union(String, Option<Vec<Encoding>>),
+    /// `bwidth`
+    BitField(uint),
+    /// A qualifier such as `r` (`const`) applied to a type.
+    Qualified(Qualifier, Box<Encoding>),
+}
+
+/// The reason an encoding string could not be parsed.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ParseError {
+    /// The byte offset at which parsing failed.
+    pub offset: uint,
+    pub reason: &'static str,
+}
+
+/// The parsed encoding of a method's signature, as returned by `method_getTypeEncoding`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct MethodEncoding {
+    pub ret: Encoding,
+    /// The arguments, including the receiver and the selector.
+    pub args: Vec<Encoding>,
+}
+
+impl Encoding {
+    /// Parses a single complete type encoding.
+    pub fn parse(s: &str) -> Result<Encoding, ParseError> {
+        let mut parser = Parser::new(s);
+        let encoding = try!(parser.parse_type());
+        try!(parser.expect_end());
+        Ok(encoding)
+    }
+
+    /// Returns this encoding with any qualifiers removed.
+    pub fn unqualified<'a>(&'a self) -> &'a Encoding {
+        match *self {
+            Qualified(_, ref inner) => inner.unqualified(),
+            _ => self,
+        }
+    }
+
+    /// Returns whether a value of this type can be used where `other` is expected. Qualifiers, the
+    /// class names of objects and struct fields omitted on either side are ignored; `id`, `Class`
+    /// and blocks are interchangeable, as are `void *` and other pointers, and `BOOL` as a `char`
+    /// or a `bool`.
+    pub fn is_compatible_with(&self, other: &Encoding) -> bool {
+        match (self.unqualified(), other.unqualified()) {
+            (&Id(_), &Id(_)) | (&Id(_), &Class) | (&Class, &Id(_)) |
+            (&Id(_), &Block) | (&Block, &Id(_)) => true,
+            (&Char, &Bool) | (&Bool, &Char) => true,
+            (&Int, &Long) | (&Long, &Int) => true,
+            (&UnsignedInt, &UnsignedLong) | (&UnsignedLong, &UnsignedInt) => true,
+            (&Pointer(ref a), &Pointer(ref b)) => {
+                match (a.unqualified(), b.unqualified()) {
+                    (&Void, _) | (_, &Void) | (&Unknown, _) | (_, &Unknown) => true,
+                    (a, b) => a.is_compatible_with(b),
+                }
+            }
+            (&Array(n, ref a), &Array(m, ref b)) => n == m && a.is_compatible_with(&**b),
+            (&Struct(ref n1, ref f1), &Struct(ref n2, ref f2)) |
+            (&Union(ref n1, ref f1), &Union(ref n2, ref f2)) => {
+                let names_match = n1 == n2 || n1.as_slice() == "?" || n2.as_slice() == "?" ||
+                    same_geometry_struct(n1.as_slice(), n2.as_slice());
+                names_match && match (f1, f2) {
+                    (&Some(ref f1), &Some(ref f2)) => {
+                        f1.len() == f2.len() &&
+                            f1.iter().zip(f2.iter()).all(|(a, b)| a.is_compatible_with(b))
+                    }
+                    _ => true,
+                }
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Returns a C-like description of the type, e.g. `struct CGPoint { double; double; }`.
+    pub fn describe(&self) -> String {
+        match *self {
+            Char => "char".to_string(),
+            UnsignedChar => "unsigned char".to_string(),
+            Short => "short".to_string(),
+            UnsignedShort => "unsigned short".to_string(),
+            Int => "int".to_string(),
+            UnsignedInt => "unsigned int".to_string(),
+            Long => "long".to_string(),
+            UnsignedLong => "unsigned long".to_string(),
+            LongLong => "long long".to_string(),
+            UnsignedLongLong => "unsigned long long".to_string(),
+            Float => "float".to_string(),
+            Double => "double".to_string(),
+            LongDouble => "long double".to_string(),
+            Bool => "bool".to_string(),
+            Void => "void".to_string(),
+            CString => "char *".to_string(),
+            Id(None) => "id".to_string(),
+            Id(Some(ref class)) => format!("{} *", class),
+            Block => "block".to_string(),
+            Class => "Class".to_string(),
+            Sel => "SEL".to_string(),
+            Unknown => "?".to_string(),
+            Pointer(ref inner) => format!("{} *", inner.describe()),
+            Array(count, ref inner) => format!("{}[{}]", inner.describe(), count),
+            Struct(ref name, ref fields) => describe_aggregate("struct", name.as_slice(), fields),
+            Union(ref name, ref fields) => describe_aggregate("union", name.as_slice(), fields),
+            BitField(width) => format!("unsigned int : {}", width),
+            Qualified(qualifier, ref inner) => {
+                let qualifier = match qualifier {
+                    Const => "const",
+                    In => "in",
+                    InOut => "inout",
+                    Out => "out",
+                    Bycopy => "bycopy",
+                    Byref => "byref",
+                    Oneway => "oneway",
+                };
+                format!("{} {}", qualifier, inner.describe())
+            }
+        }
+    }
+}
+
+fn describe_aggregate(keyword: &str, name: &str, fields: &Option<Vec<Encoding>>) -> String {
+    let mut result = format!("{} {}", keyword, name);
+    match *fields {
+        None => {}
+        Some(ref fields) => {
+            result.push_str(" {");
+            for field in fields.iter() {
+                result.push_str(format!(" {};", field.describe()).as_slice());
+            }
+            result.push_str(" }");
+        }
+    }
+    result
+}
+
+// 32-bit AppKit and GNUstep tag the geometry structs `_NSPoint`, `_NSSize` and `_NSRect`, while
+// 64-bit AppKit declares them as the Core Graphics structs, so the tags are interchangeable.
+fn same_geometry_struct(a: &str, b: &str) -> bool {
+    match (a, b) {
+        ("_NSPoint", "CGPoint") | ("CGPoint", "_NSPoint") |
+        ("_NSSize", "CGSize") | ("CGSize", "_NSSize") |
+        ("_NSRect", "CGRect") | ("CGRect", "_NSRect") => true,
+        _ => false,
+    }
+}
+
+impl fmt::Show for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Char => write!(f, "c"),
+            UnsignedChar => write!(f, "C"),
+            Short => write!(f, "s"),
+            UnsignedShort => write!(f, "S"),
+            Int => write!(f, "i"),
+            UnsignedInt => write!(f, "I"),
+            Long => write!(f, "l"),
+            UnsignedLong => write!(f, "L"),
+            LongLong => write!(f, "q"),
+            UnsignedLongLong => write!(f, "Q"),
+            Float => write!(f, "f"),
+            Double => write!(f, "d"),
+            LongDouble => write!(f, "D"),
+            Bool => write!(f, "B"),
+            Void => write!(f, "v"),
+            CString => write!(f, "*"),
+            Id(None) => write!(f, "@"),
+            Id(Some(ref class)) => write!(f, "@\"{}\"", class),
+            Block => write!(f, "@?"),
+            Class => write!(f, "#"),
+            Sel => write!(f, ":"),
+            Unknown => write!(f, "?"),
+            Pointer(ref inner) => write!(f, "^{}", inner),
+            Array(count, ref inner) => write!(f, "[{}{}]", count, inner),
+            Struct(ref name, ref fields) => write_aggregate(f, '{', '}', name.as_slice(), fields),
+            Union(ref name, ref fields) => write_aggregate(f, '(', ')', name.as_slice(), fields),
+            BitField(width) => write!(f, "b{}", width),
+            Qualified(qualifier, ref inner) => write!(f, "{}{}", qualifier.code(), inner),
+        }
+    }
+}
+
+fn write_aggregate(f: &mut fmt::Formatter, open: char, close: char, name: &str,
+                   fields: &Option<Vec<Encoding>>) -> fmt::Result {
+    try!(write!(f, "{}{}", open, name));
+    match *fields {
+        None => {}
+        Some(ref fields) => {
+            try!(write!(f, "="));
+            for field in fields.iter() {
+                try!(write!(f, "{}", field));
+            }
+        }
+    }
+    write!(f, "{}", close)
+}
+
+impl MethodEncoding {
+    /// Parses a method signature such as `v24@0:8@16`. The stack offsets that follow each type
+    /// are optional and are ignored.
+    pub fn parse(s: &str) -> Result<MethodEncoding, ParseError> {
+        let mut parser = Parser::new(s);
+        let ret = try!(parser.parse_type());
+        parser.skip_offset();
+        let mut args = Vec::new();
+        while !parser.at_end() {
+            args.push(try!(parser.parse_type()));
+            parser.skip_offset();
+        }
+        Ok(MethodEncoding {
+            ret: ret,
+            args: args,
+        })
+    }
+
+    /// Returns whether a method with this signature can stand in for one with the signature
+    /// `other`: the return types and each of the arguments must be compatible.
+    pub fn is_compatible_with(&self, other: &MethodEncoding) -> bool {
+        self.ret.is_compatible_with(&other.ret) &&
+            self.args.len() == other.args.len() &&
+            self.args.iter().zip(other.args.iter()).all(|(a, b)| a.is_compatible_with(b))
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: uint,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Parser<'a> {
+        Parser {
+            input: input,
+            pos: 0,
+        }
+    }
+
+    fn error<T>(&self, reason: &'static str) -> Result<T, ParseError> {
+        Err(ParseError {
+            offset: self.pos,
+            reason: reason,
+        })
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.input.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        if self.at_end() {
+            None
+        } else {
+            Some(self.input.as_bytes()[self.pos])
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), ParseError> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            self.error("unexpected trailing characters")
+        }
+    }
+
+    fn expect(&mut self, byte: u8, reason: &'static str) -> Result<(), ParseError> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(reason)
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<uint> {
+        let start = self.pos;
+        let mut value = 0u;
+        loop {
+            match self.peek() {
+                Some(digit) if digit >= b'0' && digit <= b'9' => {
+                    value = value * 10 + (digit - b'0') as uint;
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+        if self.pos == start {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    fn skip_offset(&mut self) {
+        // Offsets may be negative for arguments passed in registers on some old ABIs.
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let _ = self.parse_number();
+    }
+
+    /// Reads the name of a struct or union up to `=` or the closing delimiter.
+    fn parse_name(&mut self, close: u8) -> Result<String, ParseError> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                None => return self.error("unterminated structure or union"),
+                Some(byte) if byte == b'=' || byte == close => break,
+                Some(_) => self.pos += 1,
+            }
+        }
+        Ok(self.input.slice(start, self.pos).to_string())
+    }
+
+    fn parse_aggregate(&mut self, close: u8)
+                       -> Result<(String, Option<Vec<Encoding>>), ParseError> {
+        let name = try!(self.parse_name(close));
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok((name, None))
+        }
+        try!(self.expect(b'=', "expected '='"));
+        let mut fields = Vec::new();
+        loop {
+            match self.peek() {
+                None => return self.error("unterminated structure or union"),
+                Some(byte) if byte == close => {
+                    self.pos += 1;
+                    return Ok((name, Some(fields)))
+                }
+                Some(_) => fields.push(try!(self.parse_type())),
+            }
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<Encoding, ParseError> {
+        let code = match self.peek() {
+            None => return self.error("expected a type"),
+            Some(code) => code,
+        };
+        match Qualifier::from_code(code) {
+            Some(qualifier) => {
+                self.pos += 1;
+                let inner = try!(self.parse_type());
+                return Ok(Qualified(qualifier, box inner))
+            }
+            None => {}
+        }
+        self.pos += 1;
+        let encoding = match code {
+            b'c' => Char,
+            b'C' => UnsignedChar,
+            b's' => Short,
+            b'S' => UnsignedShort,
+            b'i' => Int,
+            b'I' => UnsignedInt,
+            b'l' => Long,
+            b'L' => UnsignedLong,
+            b'q' => LongLong,
+            b'Q' => UnsignedLongLong,
+            b'f' => Float,
+            b'd' => Double,
+            b'D' => LongDouble,
+            b'B' => Bool,
+            b'v' => Void,
+            b'*' => CString,
+            b'#' => Class,
+            b':' => Sel,
+            b'?' => Unknown,
+            b'@' => {
+                match self.peek() {
+                    Some(b'?') => {
+                        self.pos += 1;
+                        Block
+                    }
+                    Some(b'"') => {
+                        self.pos += 1;
+                        let start = self.pos;
+                        loop {
+                            match self.peek() {
+                                None => return self.error("unterminated class name"),
+                                Some(b'"') => break,
+                                Some(_) => self.pos += 1,
+                            }
+                        }
+                        let class = self.input.slice(start, self.pos).to_string();
+                        self.pos += 1;
+                        Id(Some(class))
+                    }
+                    _ => Id(None),
+                }
+            }
+            b'^' => Pointer(box try!(self.parse_type())),
+            b'[' => {
+                let count = match self.parse_number() {
+                    Some(count) => count,
+                    None => return self.error("expected an array length"),
+                };
+                let element = try!(self.parse_type());
+                try!(self.expect(b']', "expected ']'"));
+                Array(count, box element)
+            }
+            b'{' => {
+                let (name, fields) = try!(self.parse_aggregate(b'}'));
+                Struct(name, fields)
+            }
+            b'(' => {
+                let (name, fields) = try!(self.parse_aggregate(b')'));
+                Union(name, fields)
+            }
+            b'b' => {
+                match self.parse_number() {
+                    Some(width) => BitField(width),
+                    None => return self.error("expected a bitfield width"),
+                }
+            }
+            _ => {
+                self.pos -= 1;
+                return self.error("unknown type code")
+            }
+        };
+        Ok(encoding)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rand::{Rng, task_rng};
+    use super::*;
+
+    #[test]
+    pub fn test_parse_scalars() {
+        assert_eq!(Encoding::parse("c"), Ok(Char));
+        assert_eq!(Encoding::parse("Q"), Ok(UnsignedLongLong));
+        assert_eq!(Encoding::parse("@"), Ok(Id(None)));
+        assert_eq!(Encoding::parse("@?"), Ok(Block));
+        assert_eq!(Encoding::parse("@\"NSString\""), Ok(Id(Some("NSString".to_string()))));
+        assert_eq!(Encoding::parse(":"), Ok(Sel));
+        assert_eq!(Encoding::parse("b13"), Ok(BitField(13)));
+    }
+
+    #[test]
+    pub fn test_parse_compound() {
+        let point = Struct("CGPoint".to_string(), Some(vec!(Double, Double)));
+        let size = Struct("CGSize".to_string(), Some(vec!(Double, Double)));
+        let rect = Struct("CGRect".to_string(), Some(vec!(point, size)));
+        assert_eq!(Encoding::parse("{CGRect={CGPoint=dd}{CGSize=dd}}"), Ok(rect.clone()));
+        assert_eq!(Encoding::parse("^{CGRect={CGPoint=dd}{CGSize=dd}}"),
+                   Ok(Pointer(box rect)));
+        assert_eq!(Encoding::parse("^{__CFString}"),
+                   Ok(Pointer(box Struct("__CFString".to_string(), None))));
+        assert_eq!(Encoding::parse("[12^f]"), Ok(Array(12, box Pointer(box Float))));
+        assert_eq!(Encoding::parse("(?=iq)"),
+                   Ok(Union("?".to_string(), Some(vec!(Int, LongLong)))));
+        assert_eq!(Encoding::parse("r*"), Ok(Qualified(Const, box CString)));
+        assert_eq!(Encoding::parse("Vv"), Ok(Qualified(Oneway, box Void)));
+    }
+
+    #[test]
+    pub fn test_parse_errors() {
+        assert_eq!(Encoding::parse(""), Err(ParseError { offset: 0, reason: "expected a type" }));
+        assert!(Encoding::parse("{CGPoint=dd").is_err());
+        assert!(Encoding::parse("[^f]").is_err());
+        assert!(Encoding::parse("[4f").is_err());
+        assert!(Encoding::parse("ii").is_err());
+        assert_eq!(Encoding::parse("iz").unwrap_err().offset, 1);
+        assert!(Encoding::parse("@\"NSStr").is_err());
+    }
+
+    #[test]
+    pub fn test_method_encoding() {
+        let method = MethodEncoding::parse("v24@0:8{CGPoint=dd}16").unwrap();
+        assert_eq!(method.ret, Void);
+        assert_eq!(method.args, vec!(Id(None), Sel,
+                                     Struct("CGPoint".to_string(), Some(vec!(Double, Double)))));
+        let method = MethodEncoding::parse("@@:").unwrap();
+        assert_eq!(method.ret, Id(None));
+        assert_eq!(method.args.len(), 2);
+        let with_offsets = MethodEncoding::parse("c24@0:8@\"NSEvent\"16").unwrap();
+        assert!(MethodEncoding::parse("B@:@").unwrap().is_compatible_with(&with_offsets));
+        assert!(!MethodEncoding::parse("c@:").unwrap().is_compatible_with(&with_offsets));
+        assert!(!MethodEncoding::parse("c@:d").unwrap().is_compatible_with(&with_offsets));
+    }
+
+    #[test]
+    pub fn test_describe() {
+        let rect = Encoding::parse("{CGRect={CGPoint=dd}{CGSize=dd}}").unwrap();
+        assert_eq!(rect.describe().as_slice(),
+                   "struct CGRect { struct CGPoint { double; double; }; \
+                    struct CGSize { double; double; }; }");
+        assert_eq!(Encoding::parse("r^@").unwrap().describe().as_slice(), "const id *");
+        assert_eq!(Encoding::parse("[4b3]").unwrap().describe().as_slice(),
+                   "unsigned int : 3[4]");
+    }
+
+    #[test]
+    pub fn test_compatibility() {
+        let parse = |s: &str| Encoding::parse(s).unwrap();
+        assert!(parse("@").is_compatible_with(&parse("@\"NSString\"")));
+        assert!(parse("#").is_compatible_with(&parse("@")));
+        assert!(parse("r*").is_compatible_with(&parse("*")));
+        assert!(parse("^v").is_compatible_with(&parse("^{__CFString}")));
+        assert!(parse("c").is_compatible_with(&parse("B")));
+        assert!(parse("{CGPoint=dd}").is_compatible_with(&parse("{CGPoint}")));
+        assert!(parse("{?=dd}").is_compatible_with(&parse("{CGPoint=dd}")));
+        assert!(parse("{_NSRect={_NSPoint=ff}{_NSSize=ff}}")
+                    .is_compatible_with(&parse("{CGRect={CGPoint=ff}{CGSize=ff}}")));
+        assert!(parse("{CGSize=dd}").is_compatible_with(&parse("{_NSSize=dd}")));
+        assert!(!parse("{_NSPoint=dd}").is_compatible_with(&parse("{CGSize=dd}")));
+        assert!(!parse("q").is_compatible_with(&parse("Q")));
+        assert!(!parse("f").is_compatible_with(&parse("d")));
+        assert!(!parse("{CGPoint=dd}").is_compatible_with(&parse("{CGSize=dd}")));
+        assert!(!parse("{CGPoint=dd}").is_compatible_with(&parse("{CGPoint=ff}")));
+        assert!(!parse("[4i]").is_compatible_with(&parse("[5i]")));
+        assert!(!parse("^i").is_compatible_with(&parse("^d")));
+    }
+
+    #[test]
+    pub fn test_rust_types() {
+        assert_eq!(encode::<f64>(), "d");
+        assert_eq!(encode::<u16>(), "S");
+        assert_eq!(encoding::<*const u8>(), Qualified(Const, box CString));
+        assert_eq!(encoding::<()>(), Void);
+    }
+
+    fn arbitrary_name<R:Rng>(rng: &mut R) -> String {
+        let names = ["?", "CGPoint", "_NSRange", "__CFString", "Foo_Bar2"];
+        names[rng.gen_range(0, names.len())].to_string()
+    }
+
+    fn arbitrary_fields<R:Rng>(rng: &mut R, depth: uint) -> Option<Vec<Encoding>> {
+        if rng.gen_weighted_bool(5) {
+            return None
+        }
+        let mut fields: Vec<Encoding> = Vec::new();
+        for _ in range(0, rng.gen_range(0u, 4)) {
+            let mut field = arbitrary_encoding(rng, depth + 1);
+            // `@` followed by `?` reads back as a block, just as it does in the runtime.
+            let after_id = match fields.last() {
+                Some(last) => last.to_string().as_slice().ends_with("@"),
+                None => false,
+            };
+            if after_id && field == Unknown {
+                field = Void;
+            }
+            fields.push(field);
+        }
+        Some(fields)
+    }
+
+    fn arbitrary_encoding<R:Rng>(rng: &mut R, depth: uint) -> Encoding {
+        let leaves = [Char, UnsignedChar, Short, UnsignedShort, Int, UnsignedInt, Long,
+                      UnsignedLong, LongLong, UnsignedLongLong, Float, Double, LongDouble, Bool,
+                      Void, CString, Id(None), Block, Class, Sel, Unknown];
+        if depth > 3 || rng.gen_weighted_bool(2) {
+            return leaves[rng.gen_range(0, leaves.len())].clone()
+        }
+        match rng.gen_range(0u, 7) {
+            0 => Pointer(box arbitrary_encoding(rng, depth + 1)),
+            1 => Array(rng.gen_range(0u, 100), box arbitrary_encoding(rng, depth + 1)),
+            2 => Struct(arbitrary_name(rng), arbitrary_fields(rng, depth)),
+            3 => Union(arbitrary_name(rng), arbitrary_fields(rng, depth)),
+            4 => BitField(rng.gen_range(1u, 64)),
+            5 => Id(Some(arbitrary_name(rng))),
+            _ => {
+                let qualifiers = [Const, In, InOut, Out, Bycopy, Byref, Oneway];
+                let qualifier = qualifiers[rng.gen_range(0, qualifiers.len())];
+                Qualified(qualifier, box arbitrary_encoding(rng, depth + 1))
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_round_trip_property() {
+        let mut rng = task_rng();
+        for _ in range(0u, 1000) {
+            let encoding = arbitrary_encoding(&mut rng, 0);
+            let string = encoding.to_string();
+            assert_eq!(Encoding::parse(string.as_slice()), Ok(encoding.clone()));
+            assert!(!encoding.describe().is_empty());
+        }
+    }
+
+    #[test]
+    pub fn test_garbage_property() {
+        let alphabet = "cCsSiIlLqQfdDBv*@#:?^[]{}()=b0123456789\"rnNoORVxyz".as_bytes();
+        let mut rng = task_rng();
+        for _ in range(0u, 1000) {
+            let mut bytes = Vec::new();
+            for _ in range(0, rng.gen_range(0u, 16)) {
+                bytes.push(alphabet[rng.gen_range(0, alphabet.len())]);
+            }
+            let string = String::from_utf8(bytes).unwrap();
+            // Whatever the input, parsing must fail cleanly or round-trip.
+            match Encoding::parse(string.as_slice()) {
+                Ok(encoding) => {
+                    let reparsed = Encoding::parse(encoding.to_string().as_slice());
+                    assert_eq!(reparsed, Ok(encoding));
+                }
+                Err(err) => assert!(err.offset <= string.len()),
+            }
+        }
+    }
+}
@@ -0,0 +1,23 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#include <objc/objc.h>
+
+id objc_retain(id value);
+
+// Rust cannot catch Objective-C exceptions, so `exception::catch_exception` calls through here.
+// Returns the exception raised by `f`, retained, or nil if it returned normally.
+id RustCocoaTryCatch(void (*f)(void *), void *context) {
+    @try {
+        f(context);
+        return nil;
+    } @catch (id exception) {
+        return objc_retain(exception);
+    }
+}
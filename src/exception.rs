@@ -0,0 +1,232 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Catching and raising Objective-C exceptions. An exception that unwinds through Rust frames is
+//! undefined behavior, so any message that may raise should be sent inside `catch_exception`, or
+//! with `ObjCMethodCall::try_send`:
+//!
+//!     let removed: Result<(), Exception> = array.try_send("removeObjectAtIndex:", (index,));
+//!     match removed {
+//!         Ok(()) => {}
+//!         Err(exception) => println!("could not remove {}: {}", index, exception),
+//!     }
+//!
+//! The mock runtime cannot unwind, so raising aborts there; tests throw with
+//! `mock::throw_exception` instead. Unwinding through the exception handler is only tested on
+//! macOS.
+
+use base::{ObjCObject, downcast, id, nil, objc_exception_throw};
+use foundation::{NSException, NSString};
+use rc::StrongPtr;
+
+use libc;
+use std::c_str::CString;
+use std::fmt;
+use std::mem;
+
+#[cfg(not(feature = "mock-runtime"))]
+#[link(name = "cocoa_exception", kind = "static")]
+extern {
+    fn RustCocoaTryCatch(f: extern fn(*mut libc::c_void), context: *mut libc::c_void) -> id;
+}
+
+// The mock runtime aborts instead of raising, so this only catches exceptions thrown with
+// `mock::throw_exception`.
+#[cfg(feature = "mock-runtime")]
+unsafe fn RustCocoaTryCatch(f: extern fn(*mut libc::c_void), context: *mut libc::c_void) -> id {
+    f(context);
+    ::mock::take_exception()
+}
+
+/// An Objective-C exception caught by `catch_exception`.
+#[deriving(Clone)]
+pub struct Exception {
+    /// The exception's name, such as `NSInvalidArgumentException`.
+    pub name: String,
+    pub reason: String,
+    /// The exception's `userInfo` dictionary, which is owned by `object` and may be `nil`.
+    pub user_info: id,
+    /// The object that was thrown. This is an `NSException` unless some other object was thrown
+    /// with `@throw`, in which case `name` and `reason` are empty.
+    pub object: StrongPtr,
+}
+
+impl Exception {
+    unsafe fn from_object(object: StrongPtr) -> Exception {
+        match downcast::<NSException>(*object) {
+            Some(exception) => Exception {
+                name: to_string(exception.name()),
+                reason: to_string(exception.reason()),
+                user_info: exception.userInfo(),
+                object: object,
+            },
+            None => Exception {
+                name: String::new(),
+                reason: String::new(),
+                user_info: nil,
+                object: object,
+            },
+        }
+    }
+}
+
+impl fmt::Show for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.reason)
+    }
+}
+
+unsafe fn to_string(string: NSString) -> String {
+    if string.is_nil() {
+        return String::new()
+    }
+    CString::new(string.UTF8String(), false).as_str().unwrap_or("").to_string()
+}
+
+extern fn call_closure(context: *mut libc::c_void) {
+    let f: &mut || = unsafe { mem::transmute(context) };
+    (*f)()
+}
+
+/// Runs `f`, returning `Err` with the exception it raised if it raised an Objective-C exception.
+/// Rust values that `f` owned when the exception was raised are leaked rather than dropped.
+/// `f` must not fail, since a Rust failure cannot unwind through the exception handler; this
+/// includes messages that fail verification with the `verify` feature.
+pub fn catch_exception<T>(f: || -> T) -> Result<T, Exception> {
+    let mut result = None;
+    let exception = {
+        let mut call = || result = Some(f());
+        unsafe {
+            RustCocoaTryCatch(call_closure, &mut call as *mut || as *mut libc::c_void)
+        }
+    };
+    if exception == nil {
+        Ok(result.unwrap())
+    } else {
+        Err(unsafe { Exception::from_object(StrongPtr::new(exception)) })
+    }
+}
+
+/// Raises an `NSException`. The exception unwinds through the caller's frames without running
+/// their destructors, so this should only be called from a method implementation that has nothing
+/// left to drop, and that was called from Objective-C or inside `catch_exception`.
+pub unsafe fn raise(name: &str, reason: &str) -> ! {
+    let name = name.to_c_str();
+    let reason = reason.to_c_str();
+    let exception = NSException::exceptionWithName_reason_userInfo_(
+        NSString::alloc().initWithUTF8String_(name.as_ptr() as *const u8).autorelease(),
+        NSString::alloc().initWithUTF8String_(reason.as_ptr() as *const u8).autorelease(),
+        nil);
+    objc_exception_throw(exception.as_id())
+}
+
+// These talk to the real runtime.
+#[cfg(all(test, not(feature = "mock-runtime")))]
+mod test {
+    use base::{ObjCMethodCall, SEL, class, id, nil, selector};
+    use declare::ClassDecl;
+    use super::*;
+
+    extern fn always_raise(_: id, _: SEL) {
+        unsafe {
+            raise("RustTestException", "raised from Rust")
+        }
+    }
+
+    #[test]
+    pub fn test_no_exception() {
+        assert_eq!(catch_exception(|| 42u).ok(), Some(42u));
+    }
+
+    #[test]
+    pub fn test_unrecognized_selector() {
+        unsafe {
+            let obj: id = class("NSObject").send("new", ());
+            let result: Result<(), Exception> = obj.try_send("noSuchMethodAnywhere", ());
+            let exception = result.err().unwrap();
+            assert_eq!(exception.name.as_slice(), "NSInvalidArgumentException");
+            assert!(exception.reason.as_slice().contains("noSuchMethodAnywhere"));
+            let _: () = obj.send("release", ());
+        }
+    }
+
+    #[test]
+    pub fn test_raise() {
+        let mut decl = ClassDecl::new("RustExceptionTestRaiser", class("NSObject")).unwrap();
+        decl.add_method(selector("raise"), always_raise as extern fn(id, SEL)).unwrap();
+        let cls = decl.register();
+
+        unsafe {
+            let obj: id = cls.send("new", ());
+            let result: Result<(), Exception> = obj.try_send("raise", ());
+            let exception = result.err().unwrap();
+            assert_eq!(exception.name.as_slice(), "RustTestException");
+            assert_eq!(exception.reason.as_slice(), "raised from Rust");
+            assert!(exception.user_info == nil);
+            assert_eq!(format!("{}", exception).as_slice(), "RustTestException: raised from Rust");
+            let _: () = obj.send("release", ());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock-runtime"))]
+mod mock_test {
+    use base::{ObjCMethodCall, SEL, class, id, nil};
+    use libc;
+    use mock::{define_class, stub, stub_class, throw_exception};
+    use super::*;
+
+    extern fn test_name(_: id, _: SEL) -> id {
+        unsafe {
+            class("RustExceptionTestName").send("new", ())
+        }
+    }
+
+    extern fn test_reason(_: id, _: SEL) -> id {
+        unsafe {
+            class("RustExceptionTestReason").send("new", ())
+        }
+    }
+
+    extern fn name_utf8(_: id, _: SEL) -> *const libc::c_char {
+        "RustTestException\0".as_ptr() as *const libc::c_char
+    }
+
+    extern fn reason_utf8(_: id, _: SEL) -> *const libc::c_char {
+        "thrown from a test\0".as_ptr() as *const libc::c_char
+    }
+
+    #[test]
+    pub fn test_caught() {
+        define_class("NSString", "NSObject");
+        stub_class("RustExceptionTestName", "NSString", &[
+            stub("UTF8String", name_utf8 as extern fn(id, SEL) -> *const libc::c_char),
+        ]);
+        stub_class("RustExceptionTestReason", "NSString", &[
+            stub("UTF8String", reason_utf8 as extern fn(id, SEL) -> *const libc::c_char),
+        ]);
+        stub_class("NSException", "NSObject", &[
+            stub("name", test_name as extern fn(id, SEL) -> id),
+            stub("reason", test_reason as extern fn(id, SEL) -> id),
+        ]);
+
+        assert_eq!(catch_exception(|| 42u).ok(), Some(42u));
+        let result = catch_exception(|| {
+            unsafe {
+                throw_exception(class("NSException").send("new", ()));
+            }
+            42u
+        });
+        let exception = result.err().unwrap();
+        assert_eq!(exception.name.as_slice(), "RustTestException");
+        assert_eq!(exception.reason.as_slice(), "thrown from a test");
+        assert!(exception.user_info == nil);
+        assert_eq!(format!("{}", exception).as_slice(), "RustTestException: thrown from a test");
+    }
+}
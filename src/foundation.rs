@@ -0,0 +1,232 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Wrappers for the Foundation classes, which are also available from GNUstep's `gnustep-base`.
+
+use base::{ObjCMethodCall, ObjCObject, id};
+use geometry::NSAffineTransformStruct;
+use libc;
+
+#[cfg_attr(all(target_os = "macos", not(any(feature = "mock-runtime", feature = "gnustep"))),
+           link(name = "Foundation", kind = "framework"))]
+#[cfg_attr(feature = "gnustep", link(name = "gnustep-base"))]
+extern {
+    pub static NSDefaultRunLoopMode: NSString;
+}
+
+objc_class!(NSObject)
+objc_class!(NSAutoreleasePool: NSObject)
+objc_class!(NSProcessInfo: NSObject)
+objc_class!(NSString: NSObject)
+objc_class!(NSDate: NSObject)
+objc_class!(NSException: NSObject)
+objc_class!(NSAffineTransform: NSObject)
+
+impl NSAutoreleasePool {
+    pub unsafe fn new() -> NSAutoreleasePool {
+        "NSAutoreleasePool".send("new", ())
+    }
+}
+
+impl NSProcessInfo {
+    pub unsafe fn processInfo() -> NSProcessInfo {
+        "NSProcessInfo".send("processInfo", ())
+    }
+
+    pub unsafe fn processName(&self) -> NSString {
+        self.as_id().send("processName", ())
+    }
+}
+
+impl NSString {
+    pub unsafe fn alloc() -> NSString {
+        "NSString".send("alloc", ())
+    }
+
+    pub unsafe fn initWithUTF8String_(&self, c_string: *const u8) -> NSString {
+        self.as_id().send("initWithUTF8String:", (c_string,))
+    }
+
+    pub unsafe fn stringByAppendingString_(&self, other: NSString) -> NSString {
+        self.as_id().send("stringByAppendingString:", (other,))
+    }
+
+    pub unsafe fn init_str(&self, string: &str) -> NSString {
+        self.initWithUTF8String_(string.as_ptr())
+    }
+
+    pub unsafe fn UTF8String(&self) -> *const libc::c_char {
+        self.as_id().send("UTF8String", ())
+    }
+}
+
+impl NSDate {
+    pub unsafe fn distantPast() -> NSDate {
+        "NSDate".send("distantPast", ())
+    }
+
+    pub unsafe fn distantFuture() -> NSDate {
+        "NSDate".send("distantFuture", ())
+    }
+}
+
+impl NSException {
+    pub unsafe fn exceptionWithName_reason_userInfo_(name: NSString,
+                                                     reason: NSString,
+                                                     user_info: id) -> NSException {
+        "NSException".send("exceptionWithName:reason:userInfo:", (name, reason, user_info))
+    }
+
+    pub unsafe fn name(&self) -> NSString {
+        self.as_id().send("name", ())
+    }
+
+    pub unsafe fn reason(&self) -> NSString {
+        self.as_id().send("reason", ())
+    }
+
+    pub unsafe fn userInfo(&self) -> id {
+        self.as_id().send("userInfo", ())
+    }
+}
+
+impl NSAffineTransform {
+    /// Returns a new autoreleased identity transform.
+    pub unsafe fn transform() -> NSAffineTransform {
+        "NSAffineTransform".send("transform", ())
+    }
+
+    /// Returns a new autoreleased transform with the given matrix.
+    pub unsafe fn with_struct(matrix: NSAffineTransformStruct) -> NSAffineTransform {
+        let transform = NSAffineTransform::transform();
+        transform.setTransformStruct_(matrix);
+        transform
+    }
+
+    pub unsafe fn transformStruct(&self) -> NSAffineTransformStruct {
+        self.as_id().send("transformStruct", ())
+    }
+
+    pub unsafe fn setTransformStruct_(&self, matrix: NSAffineTransformStruct) {
+        self.as_id().send("setTransformStruct:", (matrix,))
+    }
+}
+
+// These talk to a real GNUstep runtime.
+#[cfg(all(test, feature = "gnustep"))]
+mod test {
+    use base::{ObjCMethodCall, ObjCObject};
+    use geometry::NSAffineTransformStruct;
+    use rc::autoreleasepool;
+    use std::c_str::CString;
+    use super::*;
+
+    #[test]
+    pub fn test_string() {
+        autoreleasepool(|_| {
+            unsafe {
+                let hello = NSString::alloc().init_str("hello, \0").autorelease();
+                let world = NSString::alloc().init_str("world\0").autorelease();
+                let greeting = hello.stringByAppendingString_(world);
+                let utf8 = CString::new(greeting.UTF8String(), false);
+                assert_eq!(utf8.as_str(), Some("hello, world"));
+
+                let object: NSObject = *greeting;
+                assert!(object.downcast::<NSString>().is_some());
+                assert!(object.downcast::<NSDate>().is_none());
+            }
+        });
+    }
+
+    #[test]
+    pub fn test_process_info() {
+        autoreleasepool(|_| {
+            unsafe {
+                let name = NSProcessInfo::processInfo().processName();
+                assert!(!name.is_nil());
+                assert!(!name.UTF8String().is_null());
+            }
+        });
+    }
+
+    #[test]
+    pub fn test_dates() {
+        unsafe {
+            let pool = NSAutoreleasePool::new();
+            let past: f64 = NSDate::distantPast().as_id().send("timeIntervalSinceReferenceDate", ());
+            let future: f64 =
+                NSDate::distantFuture().as_id().send("timeIntervalSinceReferenceDate", ());
+            assert!(past < 0.0 && future > 0.0);
+            pool.release();
+        }
+    }
+
+    #[test]
+    pub fn test_affine_transform() {
+        autoreleasepool(|_| {
+            unsafe {
+                let matrix = NSAffineTransformStruct::identity().translate(10.0, 20.0)
+                                                                .scale(2.0, 3.0);
+                assert!(NSAffineTransform::transform().transformStruct().is_identity());
+                assert_eq!(NSAffineTransform::with_struct(matrix).transformStruct(), matrix);
+            }
+        });
+    }
+}
+
+#[cfg(all(test, feature = "mock-runtime"))]
+mod mock_test {
+    use associated::{AssociationKey, associated_value, set_associated_value};
+    use base::{ObjCMethodCall, SEL, id};
+    use geometry::NSAffineTransformStruct;
+    use mock::{stub, stub_class};
+    use super::*;
+
+    static MATRIX: AssociationKey<NSAffineTransformStruct> = association_key!();
+
+    extern fn new_transform(cls: id, _: SEL) -> id {
+        unsafe {
+            cls.send("new", ())
+        }
+    }
+
+    extern fn transform_struct(this: id, _: SEL) -> NSAffineTransformStruct {
+        unsafe {
+            match associated_value(this, &MATRIX) {
+                Some(matrix) => matrix.clone(),
+                None => NSAffineTransformStruct::identity(),
+            }
+        }
+    }
+
+    extern fn set_transform_struct(this: id, _: SEL, matrix: NSAffineTransformStruct) {
+        unsafe {
+            set_associated_value(this, &MATRIX, matrix)
+        }
+    }
+
+    #[test]
+    pub fn test_affine_transform() {
+        stub_class("NSAffineTransform", "NSObject", &[
+            stub("+transform", new_transform as extern fn(id, SEL) -> id),
+            stub("transformStruct",
+                 transform_struct as extern fn(id, SEL) -> NSAffineTransformStruct),
+            stub("setTransformStruct:",
+                 set_transform_struct as extern fn(id, SEL, NSAffineTransformStruct)),
+        ]);
+
+        unsafe {
+            // Structs are passed to and returned from methods by value.
+            assert!(NSAffineTransform::transform().transformStruct().is_identity());
+            let matrix = NSAffineTransformStruct::identity().rotate_by_degrees(30.0)
+                                                            .translate(5.0, -5.0);
+            assert_eq!(NSAffineTransform::with_struct(matrix).transformStruct(), matrix);
+        }
+    }
+}
@@ -0,0 +1,987 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The geometry types shared by Foundation, AppKit and Core Graphics.
+//!
+//! The methods of `NSRect` behave exactly like the AppKit functions they are named after. Like
+//! those, and unlike the `CGRect` functions, they do not standardize rectangles with negative
+//! sizes: such rectangles are empty, and `max_x` of one is less than its `min_x`.
+//!
+//! Points, sizes and rectangles are formatted with `{}` as `NSStringFromPoint`,
+//! `NSStringFromSize` and `NSStringFromRect` format them, e.g. `{{0, 0}, {640, 480}}`, and parsed
+//! with `from_str` as leniently as `NSPointFromString` and the others parse them. Unlike those,
+//! `from_str` also reads back the `inf` and `nan` that infinite and undefined coordinates are
+//! formatted as.
+//!
+//! With the `serde` feature, points, sizes and rectangles also implement serde's `Serialize` and
+//! `Deserialize`.
+//!
+//! This module is reexported by `appkit`; it has no dependency on the runtime.
+
+use encoding::Encode;
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::from_str::FromStr;
+use std::num::FloatMath;
+use std::str;
+
+/// The floating point type used for coordinates and sizes, which is as wide as a pointer.
+#[cfg(target_word_size = "32")]
+pub type CGFloat = f32;
+#[cfg(target_word_size = "64")]
+pub type CGFloat = f64;
+
+#[deriving(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", deriving_serializable)]
+#[cfg_attr(feature = "serde", deriving_deserializable)]
+#[repr(C)]
+pub struct NSPoint {
+    pub x: CGFloat,
+    pub y: CGFloat,
+}
+
+impl NSPoint {
+    #[inline]
+    pub fn new(x: CGFloat, y: CGFloat) -> NSPoint {
+        NSPoint {
+            x: x,
+            y: y,
+        }
+    }
+
+    /// Converts the point between a coordinate system with its origin at the bottom left of a
+    /// container `height` high, as Cocoa's usually is, and one with its origin at the top left.
+    /// The conversion is the same in both directions.
+    #[inline]
+    pub fn flipped(&self, height: CGFloat) -> NSPoint {
+        NSPoint::new(self.x, height - self.y)
+    }
+}
+
+#[deriving(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", deriving_serializable)]
+#[cfg_attr(feature = "serde", deriving_deserializable)]
+#[repr(C)]
+pub struct NSSize {
+    pub width: CGFloat,
+    pub height: CGFloat,
+}
+
+impl NSSize {
+    #[inline]
+    pub fn new(width: CGFloat, height: CGFloat) -> NSSize {
+        NSSize {
+            width: width,
+            height: height,
+        }
+    }
+}
+
+/// Which edge of a rectangle `NSRect::divide` slices off.
+#[deriving(Clone, PartialEq, Show)]
+#[repr(u32)]
+pub enum NSRectEdge {
+    NSMinXEdge = 0,
+    NSMinYEdge = 1,
+    NSMaxXEdge = 2,
+    NSMaxYEdge = 3,
+}
+
+#[deriving(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", deriving_serializable)]
+#[cfg_attr(feature = "serde", deriving_deserializable)]
+#[repr(C)]
+pub struct NSRect {
+    pub origin: NSPoint,
+    pub size: NSSize,
+}
+
+impl NSRect {
+    #[inline]
+    pub fn new(origin: NSPoint, size: NSSize) -> NSRect {
+        NSRect {
+            origin: origin,
+            size: size
+        }
+    }
+
+    #[inline]
+    fn from_coordinates(x: CGFloat, y: CGFloat, width: CGFloat, height: CGFloat) -> NSRect {
+        NSRect::new(NSPoint::new(x, y), NSSize::new(width, height))
+    }
+
+    #[inline]
+    fn zero() -> NSRect {
+        NSRect::from_coordinates(0.0, 0.0, 0.0, 0.0)
+    }
+
+    /// As `NSPoint::flipped`: converts the rectangle between bottom-left and top-left origins in
+    /// a container `height` high. The size is unchanged.
+    #[inline]
+    pub fn flipped(&self, height: CGFloat) -> NSRect {
+        NSRect::new(NSPoint::new(self.origin.x, height - self.max_y()), self.size)
+    }
+
+    /// `NSMinX`
+    #[inline]
+    pub fn min_x(&self) -> CGFloat {
+        self.origin.x
+    }
+
+    /// `NSMidX`
+    #[inline]
+    pub fn mid_x(&self) -> CGFloat {
+        self.origin.x + self.size.width * 0.5
+    }
+
+    /// `NSMaxX`
+    #[inline]
+    pub fn max_x(&self) -> CGFloat {
+        self.origin.x + self.size.width
+    }
+
+    /// `NSMinY`
+    #[inline]
+    pub fn min_y(&self) -> CGFloat {
+        self.origin.y
+    }
+
+    /// `NSMidY`
+    #[inline]
+    pub fn mid_y(&self) -> CGFloat {
+        self.origin.y + self.size.height * 0.5
+    }
+
+    /// `NSMaxY`
+    #[inline]
+    pub fn max_y(&self) -> CGFloat {
+        self.origin.y + self.size.height
+    }
+
+    /// `NSIsEmptyRect`: whether the width or height is zero or negative.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        !(self.size.width > 0.0 && self.size.height > 0.0)
+    }
+
+    /// `NSIntersectsRect`. Empty rectangles intersect nothing, and rectangles that only share an
+    /// edge do not intersect.
+    pub fn intersects(&self, other: &NSRect) -> bool {
+        !(self.is_empty() || other.is_empty() ||
+          self.max_x() <= other.min_x() || other.max_x() <= self.min_x() ||
+          self.max_y() <= other.min_y() || other.max_y() <= self.min_y())
+    }
+
+    /// `NSIntersectionRect`: the area covered by both rectangles, or the zero rectangle if they
+    /// do not overlap.
+    pub fn intersection(&self, other: &NSRect) -> NSRect {
+        if self.max_x() <= other.min_x() || other.max_x() <= self.min_x() ||
+                self.max_y() <= other.min_y() || other.max_y() <= self.min_y() {
+            return NSRect::zero()
+        }
+        let x = max(self.min_x(), other.min_x());
+        let y = max(self.min_y(), other.min_y());
+        NSRect::from_coordinates(x,
+                                 y,
+                                 min(self.max_x(), other.max_x()) - x,
+                                 min(self.max_y(), other.max_y()) - y)
+    }
+
+    /// `NSUnionRect`: the smallest rectangle enclosing both. Empty rectangles are ignored, and
+    /// the zero rectangle is returned if both are empty.
+    pub fn union(&self, other: &NSRect) -> NSRect {
+        match (self.is_empty(), other.is_empty()) {
+            (true, true) => NSRect::zero(),
+            (true, false) => other.clone(),
+            (false, true) => self.clone(),
+            (false, false) => {
+                let x = min(self.min_x(), other.min_x());
+                let y = min(self.min_y(), other.min_y());
+                NSRect::from_coordinates(x,
+                                         y,
+                                         max(self.max_x(), other.max_x()) - x,
+                                         max(self.max_y(), other.max_y()) - y)
+            }
+        }
+    }
+
+    /// `NSInsetRect`: the rectangle shrunk by `dx` on the left and right and `dy` on the top and
+    /// bottom, or grown if they are negative. The result may have a negative size.
+    pub fn inset(&self, dx: CGFloat, dy: CGFloat) -> NSRect {
+        NSRect::from_coordinates(self.origin.x + dx,
+                                 self.origin.y + dy,
+                                 self.size.width - 2.0 * dx,
+                                 self.size.height - 2.0 * dy)
+    }
+
+    /// `NSOffsetRect`
+    pub fn offset(&self, dx: CGFloat, dy: CGFloat) -> NSRect {
+        NSRect::from_coordinates(self.origin.x + dx,
+                                 self.origin.y + dy,
+                                 self.size.width,
+                                 self.size.height)
+    }
+
+    /// `NSDivideRect`: splits the rectangle into a slice `amount` wide along `edge`, and the
+    /// remainder, which are returned in that order. If `amount` is larger than the rectangle the
+    /// slice is the whole rectangle and the remainder is empty; an empty rectangle divides into
+    /// two zero rectangles.
+    pub fn divide(&self, amount: CGFloat, edge: NSRectEdge) -> (NSRect, NSRect) {
+        if self.is_empty() {
+            return (NSRect::zero(), NSRect::zero())
+        }
+        let (x, y, width, height) = (self.origin.x, self.origin.y, self.size.width,
+                                     self.size.height);
+        match edge {
+            NSMinXEdge if amount > width => {
+                (self.clone(), NSRect::from_coordinates(self.max_x(), y, 0.0, height))
+            }
+            NSMinXEdge => {
+                (NSRect::from_coordinates(x, y, amount, height),
+                 NSRect::from_coordinates(x + amount, y, width - amount, height))
+            }
+            NSMinYEdge if amount > height => {
+                (self.clone(), NSRect::from_coordinates(x, self.max_y(), width, 0.0))
+            }
+            NSMinYEdge => {
+                (NSRect::from_coordinates(x, y, width, amount),
+                 NSRect::from_coordinates(x, y + amount, width, height - amount))
+            }
+            NSMaxXEdge if amount > width => {
+                (self.clone(), NSRect::from_coordinates(x, y, 0.0, height))
+            }
+            NSMaxXEdge => {
+                (NSRect::from_coordinates(self.max_x() - amount, y, amount, height),
+                 NSRect::from_coordinates(x, y, width - amount, height))
+            }
+            NSMaxYEdge if amount > height => {
+                (self.clone(), NSRect::from_coordinates(x, y, width, 0.0))
+            }
+            NSMaxYEdge => {
+                (NSRect::from_coordinates(x, self.max_y() - amount, width, amount),
+                 NSRect::from_coordinates(x, y, width, height - amount))
+            }
+        }
+    }
+
+    /// `NSIntegralRect`: the smallest rectangle with integral coordinates enclosing this one, or
+    /// the zero rectangle if this one is empty.
+    pub fn integral(&self) -> NSRect {
+        if self.is_empty() {
+            return NSRect::zero()
+        }
+        let x = self.min_x().floor();
+        let y = self.min_y().floor();
+        NSRect::from_coordinates(x, y, self.max_x().ceil() - x, self.max_y().ceil() - y)
+    }
+
+    /// `NSMouseInRect`: whether the point lies within the rectangle. A point on the edge with the
+    /// lower x coordinate is inside, and so is one on the top edge, which is the edge with the
+    /// lower y coordinate in a flipped view and the higher one otherwise.
+    pub fn contains_mouse(&self, point: &NSPoint, flipped: bool) -> bool {
+        let in_x = point.x >= self.min_x() && point.x < self.max_x();
+        if flipped {
+            in_x && point.y >= self.min_y() && point.y < self.max_y()
+        } else {
+            in_x && point.y > self.min_y() && point.y <= self.max_y()
+        }
+    }
+
+    /// `NSPointInRect`, which is `contains_mouse` in a flipped coordinate system.
+    #[inline]
+    pub fn contains_point(&self, point: &NSPoint) -> bool {
+        self.contains_mouse(point, true)
+    }
+
+    /// `NSContainsRect`: whether `other` lies entirely within this rectangle. Nothing contains
+    /// an empty rectangle.
+    pub fn contains_rect(&self, other: &NSRect) -> bool {
+        !other.is_empty() &&
+            self.min_x() <= other.min_x() && self.min_y() <= other.min_y() &&
+            self.max_x() >= other.max_x() && self.max_y() >= other.max_y()
+    }
+}
+
+// `min` and `max` as AppKit's `MIN` and `MAX` macros compute them.
+#[inline]
+fn min(a: CGFloat, b: CGFloat) -> CGFloat {
+    if a < b { a } else { b }
+}
+
+#[inline]
+fn max(a: CGFloat, b: CGFloat) -> CGFloat {
+    if a > b { a } else { b }
+}
+
+impl Add<NSPoint, NSPoint> for NSPoint {
+    #[inline]
+    fn add(&self, other: &NSPoint) -> NSPoint {
+        NSPoint::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub<NSPoint, NSPoint> for NSPoint {
+    #[inline]
+    fn sub(&self, other: &NSPoint) -> NSPoint {
+        NSPoint::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<CGFloat, NSPoint> for NSPoint {
+    #[inline]
+    fn mul(&self, factor: &CGFloat) -> NSPoint {
+        NSPoint::new(self.x * *factor, self.y * *factor)
+    }
+}
+
+impl Neg<NSPoint> for NSPoint {
+    #[inline]
+    fn neg(&self) -> NSPoint {
+        NSPoint::new(-self.x, -self.y)
+    }
+}
+
+impl Add<NSSize, NSSize> for NSSize {
+    #[inline]
+    fn add(&self, other: &NSSize) -> NSSize {
+        NSSize::new(self.width + other.width, self.height + other.height)
+    }
+}
+
+impl Sub<NSSize, NSSize> for NSSize {
+    #[inline]
+    fn sub(&self, other: &NSSize) -> NSSize {
+        NSSize::new(self.width - other.width, self.height - other.height)
+    }
+}
+
+impl Mul<CGFloat, NSSize> for NSSize {
+    #[inline]
+    fn mul(&self, factor: &CGFloat) -> NSSize {
+        NSSize::new(self.width * *factor, self.height * *factor)
+    }
+}
+
+impl fmt::Show for NSPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{}, {}}}", format_coordinate(self.x), format_coordinate(self.y))
+    }
+}
+
+impl fmt::Show for NSSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{}, {}}}", format_coordinate(self.width), format_coordinate(self.height))
+    }
+}
+
+impl fmt::Show for NSRect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{}, {}}}", self.origin, self.size)
+    }
+}
+
+// Like the Foundation functions, parsing never fails: the first numbers in the string are used,
+// wherever they are, and any that are missing are zero.
+
+impl FromStr for NSPoint {
+    fn from_str(s: &str) -> Option<NSPoint> {
+        let numbers = scan_coordinates(s, 2);
+        Some(NSPoint::new(numbers[0], numbers[1]))
+    }
+}
+
+impl FromStr for NSSize {
+    fn from_str(s: &str) -> Option<NSSize> {
+        let numbers = scan_coordinates(s, 2);
+        Some(NSSize::new(numbers[0], numbers[1]))
+    }
+}
+
+impl FromStr for NSRect {
+    fn from_str(s: &str) -> Option<NSRect> {
+        let numbers = scan_coordinates(s, 4);
+        Some(NSRect::from_coordinates(numbers[0], numbers[1], numbers[2], numbers[3]))
+    }
+}
+
+// Foundation formats coordinates with `%.17g`, which round-trips any `double`; 32-bit `CGFloat`s
+// are formatted with `%.9g`, which round-trips any `float`.
+#[cfg(target_word_size = "32")]
+fn to_exponential(value: CGFloat) -> (String, uint) {
+    (format!("{:.8e}", value), 9)
+}
+
+#[cfg(target_word_size = "64")]
+fn to_exponential(value: CGFloat) -> (String, uint) {
+    (format!("{:.16e}", value), 17)
+}
+
+// Formats a coordinate as `printf`'s `%g` does with the precision above: in exponential
+// notation if the exponent is less than -4 or at least the precision, and without trailing
+// zeros.
+fn format_coordinate(value: CGFloat) -> String {
+    if value.is_nan() {
+        return "nan".to_string()
+    }
+    if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.to_string()
+    }
+    if value == 0.0 {
+        return if 1.0 / value < 0.0 { "-0" } else { "0" }.to_string()
+    }
+
+    // Rust formats the exponential notation as e.g. `-1.2500000000000000e-3`.
+    let (exponential, precision) = to_exponential(value);
+    let exponential = exponential.as_slice();
+    let e = exponential.find('e').unwrap();
+    let exponent: int = from_str(exponential.slice_from(e + 1).trim_left_chars('+')).unwrap();
+    let (sign, mantissa) = if value < 0.0 {
+        ("-", exponential.slice(1, e))
+    } else {
+        ("", exponential.slice_to(e))
+    };
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let digits = digits.as_slice();
+
+    if exponent < -4 || exponent >= precision as int {
+        let mantissa = without_trailing_zeros(format!("{}.{}", digits.slice_to(1),
+                                                      digits.slice_from(1)));
+        let exponent_sign = if exponent < 0 { "-" } else { "+" };
+        format!("{}{}e{}{:02}", sign, mantissa, exponent_sign, exponent.abs())
+    } else if exponent >= 0 {
+        let point = exponent as uint + 1;
+        without_trailing_zeros(format!("{}{}.{}", sign, digits.slice_to(point),
+                                       digits.slice_from(point)))
+    } else {
+        let zeros = String::from_char((-exponent - 1) as uint, '0');
+        without_trailing_zeros(format!("{}0.{}{}", sign, zeros, digits))
+    }
+}
+
+fn without_trailing_zeros(number: String) -> String {
+    number.as_slice().trim_right_chars('0').trim_right_chars('.').to_string()
+}
+
+// Scans `s` for `count` numbers, skipping anything that is not part of one.
+fn scan_coordinates(s: &str, count: uint) -> Vec<CGFloat> {
+    let bytes = s.as_bytes();
+    let mut numbers = Vec::with_capacity(count);
+    let mut i = 0;
+    while i < bytes.len() && numbers.len() < count {
+        match scan_number(bytes, i) {
+            Some((number, end)) => {
+                numbers.push(number);
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+    while numbers.len() < count {
+        numbers.push(0.0);
+    }
+    numbers
+}
+
+fn count_digits(bytes: &[u8], start: uint) -> uint {
+    bytes.slice_from(start).iter().take_while(|b| **b >= b'0' && **b <= b'9').count()
+}
+
+fn is_sign(bytes: &[u8], i: uint) -> bool {
+    i < bytes.len() && (bytes[i] == b'-' || bytes[i] == b'+')
+}
+
+// Scans a number such as `-1.5`, `.5`, `2.`, `1e-3` or `-inf` starting at `start`, returning it
+// and the index just past it.
+fn scan_number(bytes: &[u8], start: uint) -> Option<(CGFloat, uint)> {
+    let negative = bytes[start] == b'-';
+    let mut end = if is_sign(bytes, start) { start + 1 } else { start };
+    if bytes.slice_from(end).starts_with(b"inf") {
+        let infinity: CGFloat = if negative { -1.0 / 0.0 } else { 1.0 / 0.0 };
+        return Some((infinity, end + 3))
+    }
+    if bytes.slice_from(end).starts_with(b"nan") {
+        return Some((0.0 / 0.0, end + 3))
+    }
+    let integer_start = end;
+    let integer_digits = count_digits(bytes, end);
+    end += integer_digits;
+    let mut fraction_start = end;
+    let mut fraction_digits = 0;
+    if end < bytes.len() && bytes[end] == b'.' {
+        fraction_start = end + 1;
+        fraction_digits = count_digits(bytes, fraction_start);
+        end = fraction_start + fraction_digits;
+    }
+    if integer_digits + fraction_digits == 0 {
+        return None
+    }
+
+    // An exponent is only part of the number if it has digits.
+    let mut exponent = String::new();
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let exponent_start = if is_sign(bytes, end + 1) { end + 2 } else { end + 1 };
+        let exponent_digits = count_digits(bytes, exponent_start);
+        if exponent_digits > 0 {
+            let exponent_end = exponent_start + exponent_digits;
+            let digits = str::from_utf8(bytes.slice(exponent_start, exponent_end)).unwrap();
+            exponent = format!("e{}{}", if bytes[end + 1] == b'-' { "-" } else { "" }, digits);
+            end = exponent_end;
+        }
+    }
+
+    // Rebuild the number in a form `from_str` accepts.
+    let integer = str::from_utf8(bytes.slice(integer_start, integer_start + integer_digits));
+    let fraction = str::from_utf8(bytes.slice(fraction_start, fraction_start + fraction_digits));
+    let text = format!("{}{}.{}{}",
+                       if negative { "-" } else { "" },
+                       if integer_digits == 0 { "0" } else { integer.unwrap() },
+                       if fraction_digits == 0 { "0" } else { fraction.unwrap() },
+                       exponent);
+    let number: f64 = from_str(text.as_slice()).unwrap();
+    Some((number as CGFloat, end))
+}
+
+/// An affine transform, laid out like both `NSAffineTransformStruct` and `CGAffineTransform`.
+/// A point `(x, y)` is transformed to `(m11 * x + m21 * y + tX, m12 * x + m22 * y + tY)`.
+///
+/// As with `NSAffineTransform`, `translate`, `scale` and `rotate_by_radians` return a transform
+/// that applies the new operation first and then this one, so that they can be chained in the
+/// order the drawing code thinks of them:
+///
+///     // Flips a view of the given height, as `isFlipped` does.
+///     let flip = NSAffineTransformStruct::identity().translate(0.0, height).scale(1.0, -1.0);
+#[deriving(Clone, PartialEq, Show)]
+#[repr(C)]
+pub struct NSAffineTransformStruct {
+    pub m11: CGFloat,
+    pub m12: CGFloat,
+    pub m21: CGFloat,
+    pub m22: CGFloat,
+    pub tX: CGFloat,
+    pub tY: CGFloat,
+}
+
+impl NSAffineTransformStruct {
+    #[inline]
+    pub fn new(m11: CGFloat, m12: CGFloat, m21: CGFloat, m22: CGFloat, tX: CGFloat, tY: CGFloat)
+               -> NSAffineTransformStruct {
+        NSAffineTransformStruct {
+            m11: m11,
+            m12: m12,
+            m21: m21,
+            m22: m22,
+            tX: tX,
+            tY: tY,
+        }
+    }
+
+    /// The transform that leaves everything where it is.
+    #[inline]
+    pub fn identity() -> NSAffineTransformStruct {
+        NSAffineTransformStruct::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == NSAffineTransformStruct::identity()
+    }
+
+    /// Returns the transform that moves by `(dx, dy)` and then applies this one.
+    pub fn translate(&self, dx: CGFloat, dy: CGFloat) -> NSAffineTransformStruct {
+        NSAffineTransformStruct::new(1.0, 0.0, 0.0, 1.0, dx, dy).concat(self)
+    }
+
+    /// Returns the transform that scales by `sx` horizontally and `sy` vertically, and then
+    /// applies this one.
+    pub fn scale(&self, sx: CGFloat, sy: CGFloat) -> NSAffineTransformStruct {
+        NSAffineTransformStruct::new(sx, 0.0, 0.0, sy, 0.0, 0.0).concat(self)
+    }
+
+    /// Returns the transform that rotates counterclockwise by `angle` radians about the origin,
+    /// and then applies this one.
+    pub fn rotate_by_radians(&self, angle: CGFloat) -> NSAffineTransformStruct {
+        let (sin, cos) = (angle.sin(), angle.cos());
+        NSAffineTransformStruct::new(cos, sin, -sin, cos, 0.0, 0.0).concat(self)
+    }
+
+    /// As `rotate_by_radians`, with the angle in degrees.
+    pub fn rotate_by_degrees(&self, angle: CGFloat) -> NSAffineTransformStruct {
+        self.rotate_by_radians(angle * (PI / 180.0) as CGFloat)
+    }
+
+    /// Returns the transform that applies this one and then `other`, as
+    /// `CGAffineTransformConcat` and `-[NSAffineTransform appendTransform:]` do.
+    pub fn concat(&self, other: &NSAffineTransformStruct) -> NSAffineTransformStruct {
+        NSAffineTransformStruct::new(self.m11 * other.m11 + self.m12 * other.m21,
+                                     self.m11 * other.m12 + self.m12 * other.m22,
+                                     self.m21 * other.m11 + self.m22 * other.m21,
+                                     self.m21 * other.m12 + self.m22 * other.m22,
+                                     self.tX * other.m11 + self.tY * other.m21 + other.tX,
+                                     self.tX * other.m12 + self.tY * other.m22 + other.tY)
+    }
+
+    /// Returns the transform that undoes this one, or `None` if it collapses the plane onto a
+    /// line or a point.
+    pub fn invert(&self) -> Option<NSAffineTransformStruct> {
+        let determinant = self.m11 * self.m22 - self.m12 * self.m21;
+        if determinant == 0.0 {
+            return None
+        }
+        Some(NSAffineTransformStruct::new(self.m22 / determinant,
+                                          -self.m12 / determinant,
+                                          -self.m21 / determinant,
+                                          self.m11 / determinant,
+                                          (self.m21 * self.tY - self.m22 * self.tX) / determinant,
+                                          (self.m12 * self.tX - self.m11 * self.tY) / determinant))
+    }
+
+    pub fn transform_point(&self, point: &NSPoint) -> NSPoint {
+        NSPoint::new(self.m11 * point.x + self.m21 * point.y + self.tX,
+                     self.m12 * point.x + self.m22 * point.y + self.tY)
+    }
+
+    /// Transforms a size, which is a distance and so is not translated. The result may have a
+    /// negative width or height.
+    pub fn transform_size(&self, size: &NSSize) -> NSSize {
+        NSSize::new(self.m11 * size.width + self.m21 * size.height,
+                    self.m12 * size.width + self.m22 * size.height)
+    }
+
+    /// Returns the smallest rectangle enclosing the transformed corners of `rect`, as
+    /// `CGRectApplyAffineTransform` does.
+    pub fn transform_rect(&self, rect: &NSRect) -> NSRect {
+        let corners = [
+            self.transform_point(&NSPoint::new(rect.min_x(), rect.min_y())),
+            self.transform_point(&NSPoint::new(rect.max_x(), rect.min_y())),
+            self.transform_point(&NSPoint::new(rect.min_x(), rect.max_y())),
+            self.transform_point(&NSPoint::new(rect.max_x(), rect.max_y())),
+        ];
+        let (mut min_x, mut min_y) = (corners[0].x, corners[0].y);
+        let (mut max_x, mut max_y) = (min_x, min_y);
+        for corner in corners.iter().skip(1) {
+            min_x = min(min_x, corner.x);
+            min_y = min(min_y, corner.y);
+            max_x = max(max_x, corner.x);
+            max_y = max(max_y, corner.y);
+        }
+        NSRect::from_coordinates(min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+}
+
+// The encodings of the geometry types for each width of `CGFloat`.
+struct Encodings {
+    point: &'static str,
+    size: &'static str,
+    rect: &'static str,
+    transform: &'static str,
+}
+
+static ENCODINGS_32: Encodings = Encodings {
+    point: "{CGPoint=ff}",
+    size: "{CGSize=ff}",
+    rect: "{CGRect={CGPoint=ff}{CGSize=ff}}",
+    transform: "{_NSAffineTransformStruct=ffffff}",
+};
+
+static ENCODINGS_64: Encodings = Encodings {
+    point: "{CGPoint=dd}",
+    size: "{CGSize=dd}",
+    rect: "{CGRect={CGPoint=dd}{CGSize=dd}}",
+    transform: "{_NSAffineTransformStruct=dddddd}",
+};
+
+#[cfg(target_word_size = "32")]
+static ENCODINGS: &'static Encodings = &ENCODINGS_32;
+#[cfg(target_word_size = "64")]
+static ENCODINGS: &'static Encodings = &ENCODINGS_64;
+
+impl Encode for NSPoint {
+    fn encode(_: Option<NSPoint>) -> &'static str {
+        ENCODINGS.point
+    }
+}
+
+impl Encode for NSSize {
+    fn encode(_: Option<NSSize>) -> &'static str {
+        ENCODINGS.size
+    }
+}
+
+impl Encode for NSRect {
+    fn encode(_: Option<NSRect>) -> &'static str {
+        ENCODINGS.rect
+    }
+}
+
+impl Encode for NSAffineTransformStruct {
+    fn encode(_: Option<NSAffineTransformStruct>) -> &'static str {
+        ENCODINGS.transform
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use encoding::{Double, Encoding, Float, Struct, encode, encoding};
+    use std::mem;
+    use super::{ENCODINGS_32, ENCODINGS_64, Encodings};
+    use super::*;
+
+    fn check_encodings(encodings: &Encodings, float: Encoding) {
+        let point = Struct("CGPoint".to_string(), Some(vec!(float.clone(), float.clone())));
+        let size = Struct("CGSize".to_string(), Some(vec!(float.clone(), float.clone())));
+        assert_eq!(Encoding::parse(encodings.point), Ok(point.clone()));
+        assert_eq!(Encoding::parse(encodings.size), Ok(size.clone()));
+        assert_eq!(Encoding::parse(encodings.rect),
+                   Ok(Struct("CGRect".to_string(), Some(vec!(point, size)))));
+        assert_eq!(Encoding::parse(encodings.transform),
+                   Ok(Struct("_NSAffineTransformStruct".to_string(),
+                             Some(Vec::from_elem(6, float)))));
+    }
+
+    #[test]
+    pub fn test_encodings() {
+        check_encodings(&ENCODINGS_32, Float);
+        check_encodings(&ENCODINGS_64, Double);
+
+        // Methods declared by 32-bit AppKit and GNUstep use the `_NS` struct tags.
+        let float = encode::<CGFloat>();
+        let appkit_rect = format!("{{_NSRect={{_NSPoint={0}{0}}}{{_NSSize={0}{0}}}}}", float);
+        let appkit_rect = Encoding::parse(appkit_rect.as_slice()).unwrap();
+        assert!(encoding::<NSRect>().is_compatible_with(&appkit_rect));
+        let appkit_point = format!("{{_NSPoint={0}{0}}}", float);
+        assert!(encoding::<NSPoint>()
+                    .is_compatible_with(&Encoding::parse(appkit_point.as_slice()).unwrap()));
+    }
+
+    // `make check-32` runs this for a 32-bit target as well.
+    #[test]
+    pub fn test_layout() {
+        assert_eq!(mem::size_of::<CGFloat>(), mem::size_of::<uint>());
+        assert_eq!(mem::size_of::<NSPoint>(), 2 * mem::size_of::<CGFloat>());
+        assert_eq!(mem::size_of::<NSSize>(), 2 * mem::size_of::<CGFloat>());
+        assert_eq!(mem::size_of::<NSRect>(), 4 * mem::size_of::<CGFloat>());
+        assert_eq!(mem::min_align_of::<NSRect>(), mem::min_align_of::<CGFloat>());
+        assert_eq!(mem::size_of::<NSAffineTransformStruct>(), 6 * mem::size_of::<CGFloat>());
+
+        let rect = NSRect::new(NSPoint::new(1.0, 2.0), NSSize::new(3.0, 4.0));
+        let fields: [CGFloat, ..4] = unsafe { mem::transmute(rect) };
+        assert_eq!(fields, [1.0, 2.0, 3.0, 4.0]);
+
+        let float = encoding::<CGFloat>();
+        assert_eq!(encode::<CGFloat>(), if mem::size_of::<CGFloat>() == 4 { "f" } else { "d" });
+        let point = Struct("CGPoint".to_string(), Some(vec!(float.clone(), float)));
+        assert_eq!(encoding::<NSPoint>(), point);
+    }
+
+    fn rect(x: CGFloat, y: CGFloat, width: CGFloat, height: CGFloat) -> NSRect {
+        NSRect::new(NSPoint::new(x, y), NSSize::new(width, height))
+    }
+
+    #[test]
+    pub fn test_edges() {
+        let r = rect(10.0, 20.0, 30.0, 40.0);
+        assert_eq!((r.min_x(), r.mid_x(), r.max_x()), (10.0, 25.0, 40.0));
+        assert_eq!((r.min_y(), r.mid_y(), r.max_y()), (20.0, 40.0, 60.0));
+        assert!(!r.is_empty());
+
+        // Negative sizes are not standardized.
+        let negative = rect(10.0, 20.0, -30.0, 40.0);
+        assert_eq!((negative.min_x(), negative.max_x()), (10.0, -20.0));
+        assert!(negative.is_empty());
+        assert!(rect(0.0, 0.0, 10.0, 0.0).is_empty());
+    }
+
+    #[test]
+    pub fn test_intersection_and_union() {
+        let a = rect(0.0, 0.0, 10.0, 10.0);
+        let b = rect(5.0, 5.0, 10.0, 10.0);
+        assert!(a.intersects(&b));
+        assert_eq!(a.intersection(&b), rect(5.0, 5.0, 5.0, 5.0));
+        assert_eq!(a.union(&b), rect(0.0, 0.0, 15.0, 15.0));
+
+        // Touching edges do not intersect.
+        let beside = rect(10.0, 0.0, 10.0, 10.0);
+        assert!(!a.intersects(&beside));
+        assert_eq!(a.intersection(&beside), rect(0.0, 0.0, 0.0, 0.0));
+
+        // Empty rectangles are ignored by the union, and intersect nothing.
+        let empty = rect(100.0, 100.0, 0.0, 5.0);
+        assert!(!a.intersects(&empty));
+        assert_eq!(a.union(&empty), a);
+        assert_eq!(empty.union(&a), a);
+        assert_eq!(empty.union(&empty), rect(0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    pub fn test_inset_offset_integral() {
+        let r = rect(10.0, 20.0, 30.0, 40.0);
+        assert_eq!(r.inset(5.0, -5.0), rect(15.0, 15.0, 20.0, 50.0));
+        assert_eq!(r.inset(20.0, 0.0), rect(30.0, 20.0, -10.0, 40.0));
+        assert_eq!(r.offset(-10.0, 5.0), rect(0.0, 25.0, 30.0, 40.0));
+        assert_eq!(rect(0.5, -0.5, 1.0, 1.25).integral(), rect(0.0, -1.0, 2.0, 2.0));
+        assert_eq!(rect(0.5, 0.5, 1.0, -1.0).integral(), rect(0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    pub fn test_divide() {
+        let r = rect(0.0, 0.0, 100.0, 50.0);
+        assert_eq!(r.divide(30.0, NSMinXEdge), (rect(0.0, 0.0, 30.0, 50.0),
+                                                rect(30.0, 0.0, 70.0, 50.0)));
+        assert_eq!(r.divide(30.0, NSMaxXEdge), (rect(70.0, 0.0, 30.0, 50.0),
+                                                rect(0.0, 0.0, 70.0, 50.0)));
+        assert_eq!(r.divide(20.0, NSMinYEdge), (rect(0.0, 0.0, 100.0, 20.0),
+                                                rect(0.0, 20.0, 100.0, 30.0)));
+        assert_eq!(r.divide(20.0, NSMaxYEdge), (rect(0.0, 30.0, 100.0, 20.0),
+                                                rect(0.0, 0.0, 100.0, 30.0)));
+        assert_eq!(r.divide(200.0, NSMinXEdge), (r, rect(100.0, 0.0, 0.0, 50.0)));
+        assert_eq!(r.divide(200.0, NSMaxYEdge), (r, rect(0.0, 0.0, 100.0, 0.0)));
+        let zero = rect(0.0, 0.0, 0.0, 0.0);
+        assert_eq!(rect(5.0, 5.0, 0.0, 10.0).divide(1.0, NSMinXEdge), (zero, zero));
+    }
+
+    #[test]
+    pub fn test_containment() {
+        let r = rect(0.0, 0.0, 10.0, 10.0);
+        assert!(r.contains_point(&NSPoint::new(0.0, 0.0)));
+        assert!(!r.contains_point(&NSPoint::new(10.0, 5.0)));
+        assert!(!r.contains_point(&NSPoint::new(5.0, 10.0)));
+        // Unflipped, the top edge is the one with the higher y coordinate.
+        assert!(!r.contains_mouse(&NSPoint::new(5.0, 0.0), false));
+        assert!(r.contains_mouse(&NSPoint::new(5.0, 10.0), false));
+        assert!(r.contains_mouse(&NSPoint::new(0.0, 5.0), false));
+        assert!(!rect(0.0, 0.0, -10.0, 10.0).contains_point(&NSPoint::new(-5.0, 5.0)));
+
+        assert!(r.contains_rect(&rect(0.0, 0.0, 10.0, 10.0)));
+        assert!(r.contains_rect(&rect(2.0, 2.0, 5.0, 5.0)));
+        assert!(!r.contains_rect(&rect(2.0, 2.0, 10.0, 5.0)));
+        assert!(!r.contains_rect(&rect(2.0, 2.0, 0.0, 5.0)));
+    }
+
+    #[test]
+    pub fn test_flipped() {
+        assert_eq!(NSPoint::new(5.0, 10.0).flipped(100.0), NSPoint::new(5.0, 90.0));
+        let r = rect(10.0, 20.0, 30.0, 40.0);
+        assert_eq!(r.flipped(100.0), rect(10.0, 40.0, 30.0, 40.0));
+        assert_eq!(r.flipped(100.0).flipped(100.0), r);
+        // A rectangle covering the container stays where it is.
+        assert_eq!(rect(0.0, 0.0, 50.0, 100.0).flipped(100.0), rect(0.0, 0.0, 50.0, 100.0));
+    }
+
+    #[test]
+    pub fn test_format() {
+        assert_eq!(format!("{}", NSPoint::new(0.0, -0.0)).as_slice(), "{0, -0}");
+        assert_eq!(format!("{}", NSSize::new(640.0, 0.25)).as_slice(), "{640, 0.25}");
+        assert_eq!(format!("{}", rect(1.5, -2.25, 3.0, 4.0)).as_slice(),
+                   "{{1.5, -2.25}, {3, 4}}");
+        assert_eq!(format!("{}", NSPoint::new(1.0 / 0.0, 0.0 / 0.0)).as_slice(), "{inf, nan}");
+    }
+
+    #[cfg(target_word_size = "64")]
+    #[test]
+    pub fn test_format_precision() {
+        assert_eq!(format!("{}", NSPoint::new(0.1, 0.001)).as_slice(),
+                   "{0.10000000000000001, 0.001}");
+        assert_eq!(format!("{}", NSSize::new(1e20, 1.0 / 1048576.0)).as_slice(),
+                   "{1e+20, 9.5367431640625e-07}");
+        assert_eq!(format!("{}", NSSize::new(12345678901234567.0, -0.0001)).as_slice(),
+                   "{12345678901234568, -0.0001}");
+    }
+
+    #[cfg(target_word_size = "32")]
+    #[test]
+    pub fn test_format_precision() {
+        assert_eq!(format!("{}", NSPoint::new(0.1, 0.001)).as_slice(),
+                   "{0.100000001, 0.00100000005}");
+        assert_eq!(format!("{}", NSSize::new(1e20, 1.0 / 1048576.0)).as_slice(),
+                   "{1.00000002e+20, 9.53674316e-07}");
+    }
+
+    #[test]
+    pub fn test_parse() {
+        assert_eq!(from_str::<NSRect>("{{1, 2}, {3, 4}}"), Some(rect(1.0, 2.0, 3.0, 4.0)));
+        assert_eq!(from_str::<NSPoint>("{ -1.5 ,+2e1}"), Some(NSPoint::new(-1.5, 20.0)));
+        assert_eq!(from_str::<NSSize>("{1.,.5}"), Some(NSSize::new(1.0, 0.5)));
+        assert_eq!(from_str::<NSSize>("{2.5E+2, 2e}"), Some(NSSize::new(250.0, 2.0)));
+        // Anything that is not a number is skipped, and missing numbers are zero.
+        assert_eq!(from_str::<NSSize>("width: 3"), Some(NSSize::new(3.0, 0.0)));
+        assert_eq!(from_str::<NSPoint>("-{x}- 2"), Some(NSPoint::new(2.0, 0.0)));
+        assert_eq!(from_str::<NSRect>("{{1, 2}, {3}}"), Some(rect(1.0, 2.0, 3.0, 0.0)));
+        assert_eq!(from_str::<NSRect>("nothing"), Some(rect(0.0, 0.0, 0.0, 0.0)));
+        assert_eq!(from_str::<NSPoint>("{1, 2, 3}"), Some(NSPoint::new(1.0, 2.0)));
+
+        let r = rect(0.5, -2.25, 1e20, 640.0);
+        assert_eq!(from_str::<NSRect>(format!("{}", r).as_slice()), Some(r));
+        let infinite = NSSize::new(1.0 / 0.0, -1.0 / 0.0);
+        assert_eq!(from_str::<NSSize>(format!("{}", infinite).as_slice()), Some(infinite));
+        let undefined = from_str::<NSPoint>("{nan, 1}").unwrap();
+        assert!(undefined.x.is_nan() && undefined.y == 1.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde() {
+        use serde::json;
+
+        let r = rect(1.5, -2.0, 3.0, 4.0);
+        let encoded = json::to_string(&r).unwrap();
+        assert!(encoded.as_slice().starts_with(r#"{"origin":{"x":1.5,"#));
+        assert_eq!(json::from_str::<NSRect>(encoded.as_slice()).unwrap(), r);
+    }
+
+    #[test]
+    pub fn test_operators() {
+        let p = NSPoint::new(1.0, 2.0);
+        assert_eq!(p + NSPoint::new(3.0, 4.0), NSPoint::new(4.0, 6.0));
+        assert_eq!(p - NSPoint::new(3.0, 4.0), NSPoint::new(-2.0, -2.0));
+        assert_eq!(p * 2.0, NSPoint::new(2.0, 4.0));
+        assert_eq!(-p, NSPoint::new(-1.0, -2.0));
+        let s = NSSize::new(10.0, 20.0);
+        assert_eq!(s + NSSize::new(1.0, 2.0), NSSize::new(11.0, 22.0));
+        assert_eq!(s - NSSize::new(1.0, 2.0), NSSize::new(9.0, 18.0));
+        assert_eq!(s * 0.5, NSSize::new(5.0, 10.0));
+    }
+
+    fn assert_close(actual: NSPoint, expected: NSPoint) {
+        assert!((actual.x - expected.x).abs() < 1e-5 && (actual.y - expected.y).abs() < 1e-5,
+                "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    pub fn test_transforms() {
+        let identity = NSAffineTransformStruct::identity();
+        assert!(identity.is_identity());
+        let p = NSPoint::new(1.0, 2.0);
+        assert_eq!(identity.transform_point(&p), p);
+
+        // The most recently added operation is applied first.
+        let t = identity.translate(10.0, 20.0).scale(2.0, 3.0);
+        assert_eq!(t.transform_point(&p), NSPoint::new(12.0, 26.0));
+        assert_eq!(t.transform_size(&NSSize::new(1.0, 1.0)), NSSize::new(2.0, 3.0));
+        let scale_then_translate = NSAffineTransformStruct::identity().scale(2.0, 3.0)
+                                                                      .translate(10.0, 20.0);
+        assert_eq!(scale_then_translate.transform_point(&p), NSPoint::new(22.0, 66.0));
+        assert_eq!(identity.scale(2.0, 3.0).concat(&identity.translate(10.0, 20.0)), t);
+
+        let rotation = identity.rotate_by_degrees(90.0);
+        assert_close(rotation.transform_point(&p), NSPoint::new(-2.0, 1.0));
+
+        let inverse = t.invert().unwrap();
+        assert_close(inverse.transform_point(&NSPoint::new(12.0, 26.0)), p);
+        assert_close(t.concat(&inverse).transform_point(&p), p);
+        assert!(identity.scale(0.0, 1.0).invert().is_none());
+
+        // A flip maps a rectangle to one with a positive size.
+        let flip = identity.translate(0.0, 100.0).scale(1.0, -1.0);
+        assert_eq!(flip.transform_rect(&rect(10.0, 10.0, 20.0, 30.0)),
+                   rect(10.0, 60.0, 20.0, 30.0));
+        let bounds = rotation.transform_rect(&rect(0.0, 0.0, 2.0, 1.0));
+        assert_close(bounds.origin, NSPoint::new(-1.0, 0.0));
+        assert_close(NSPoint::new(bounds.size.width, bounds.size.height), NSPoint::new(1.0, 2.0));
+    }
+}
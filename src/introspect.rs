@@ -0,0 +1,244 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Inspecting the classes registered with the runtime, e.g. to list what a class responds to:
+//!
+//!     for method in all_methods(class("MyView")).iter() {
+//!         println!("{} {}", method.name, method.encoding);
+//!     }
+
+use base;
+use base::{Class, SEL, c_string, nil, object_getClass};
+use base::{class_copyIvarList, class_copyMethodList, class_copyPropertyList};
+use base::{class_copyProtocolList, class_getInstanceSize, class_getName, class_getSuperclass};
+use base::{ivar_getName, ivar_getOffset, ivar_getTypeEncoding, method_getName};
+use base::{method_getTypeEncoding, objc_copyClassList, property_getAttributes};
+use base::{property_getName, protocol_getName, sel_getName};
+
+use libc;
+use std::collections::HashSet;
+
+/// A method, as listed by `methods`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Method {
+    pub selector: SEL,
+    pub name: String,
+    /// The method's type encoding, e.g. `v@:@` for a method that takes an object.
+    pub encoding: String,
+}
+
+/// An instance variable, as listed by `ivars`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Ivar {
+    pub name: String,
+    pub encoding: String,
+    /// The offset of the variable from the start of an instance, in bytes.
+    pub offset: int,
+}
+
+/// A declared property, as listed by `properties`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Property {
+    pub name: String,
+    /// The property's attribute string, e.g. `T@"NSString",C,N,V_title`.
+    pub attributes: String,
+}
+
+// Converts a list copied out of the runtime and frees it.
+unsafe fn copied_list<T,U>(list: *mut T, count: libc::c_uint, f: |&T| -> U) -> Vec<U> {
+    let mut result = Vec::with_capacity(count as uint);
+    for i in range(0, count as int) {
+        result.push(f(&*list.offset(i)));
+    }
+    libc::free(list as *mut libc::c_void);
+    result
+}
+
+/// Returns every class registered with the runtime.
+pub fn classes() -> Vec<Class> {
+    unsafe {
+        let mut count = 0;
+        let list = objc_copyClassList(&mut count);
+        copied_list(list, count, |cls| *cls)
+    }
+}
+
+/// Returns the name of `cls`.
+pub fn name(cls: Class) -> String {
+    unsafe {
+        c_string(class_getName(cls))
+    }
+}
+
+/// Returns the superclass of `cls`, or `None` for a root class.
+pub fn superclass(cls: Class) -> Option<Class> {
+    let superclass = unsafe { class_getSuperclass(cls) };
+    if superclass == nil {
+        None
+    } else {
+        Some(superclass)
+    }
+}
+
+/// Returns the superclasses of `cls`, starting with its own superclass and ending with the root
+/// class.
+pub fn superclasses(cls: Class) -> Vec<Class> {
+    let mut result = Vec::new();
+    let mut cls = cls;
+    loop {
+        match superclass(cls) {
+            Some(superclass) => {
+                result.push(superclass);
+                cls = superclass;
+            }
+            None => return result,
+        }
+    }
+}
+
+/// Returns the metaclass of `cls`, whose instance methods are the class methods of `cls`.
+pub fn metaclass(cls: Class) -> Class {
+    unsafe {
+        object_getClass(cls)
+    }
+}
+
+/// Returns the size of an instance of `cls` in bytes.
+pub fn instance_size(cls: Class) -> uint {
+    unsafe {
+        class_getInstanceSize(cls) as uint
+    }
+}
+
+/// Returns the instance methods `cls` implements itself, not counting inherited ones. The class
+/// methods are the instance methods of the metaclass.
+pub fn methods(cls: Class) -> Vec<Method> {
+    unsafe {
+        let mut count = 0;
+        let list = class_copyMethodList(cls, &mut count);
+        copied_list(list, count, |method| {
+            let selector = method_getName(*method);
+            Method {
+                selector: selector,
+                name: c_string(sel_getName(selector)),
+                encoding: c_string(method_getTypeEncoding(*method)),
+            }
+        })
+    }
+}
+
+/// Returns every instance method `cls` responds to, including inherited ones. Where a class
+/// overrides a method of its superclass, only the override is listed.
+pub fn all_methods(cls: Class) -> Vec<Method> {
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    let mut classes = vec![cls];
+    classes.push_all(superclasses(cls).as_slice());
+    for cls in classes.iter() {
+        for method in methods(*cls).into_iter() {
+            if seen.insert(method.selector.as_ptr() as uint) {
+                result.push(method);
+            }
+        }
+    }
+    result
+}
+
+/// Returns the instance variables `cls` declares itself.
+pub fn ivars(cls: Class) -> Vec<Ivar> {
+    unsafe {
+        let mut count = 0;
+        let list = class_copyIvarList(cls, &mut count);
+        copied_list(list, count, |ivar| {
+            Ivar {
+                name: c_string(ivar_getName(*ivar)),
+                encoding: c_string(ivar_getTypeEncoding(*ivar)),
+                offset: ivar_getOffset(*ivar) as int,
+            }
+        })
+    }
+}
+
+/// Returns the properties `cls` declares itself.
+pub fn properties(cls: Class) -> Vec<Property> {
+    unsafe {
+        let mut count = 0;
+        let list = class_copyPropertyList(cls, &mut count);
+        copied_list(list, count, |property| {
+            Property {
+                name: c_string(property_getName(*property)),
+                attributes: c_string(property_getAttributes(*property)),
+            }
+        })
+    }
+}
+
+/// Returns the names of the protocols `cls` adopts itself.
+pub fn protocols(cls: Class) -> Vec<String> {
+    unsafe {
+        let mut count = 0;
+        let list = class_copyProtocolList(cls, &mut count);
+        copied_list(list, count, |protocol: &*mut base::Protocol| {
+            c_string(protocol_getName(*protocol))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{SEL, class, id, selector};
+    use declare::ClassDecl;
+    use super::*;
+
+    extern fn get_zero(_: id, _: SEL) -> u32 {
+        0
+    }
+
+    extern fn describe(_: id, _: SEL) -> id {
+        0 as id
+    }
+
+    #[test]
+    pub fn test_introspection() {
+        let nsobject = class("NSObject");
+        let mut decl = ClassDecl::new("RustIntrospectionTest", nsobject).unwrap();
+        decl.add_ivar::<u32>("count").unwrap();
+        decl.add_method(selector("zero"), get_zero as extern fn(id, SEL) -> u32).unwrap();
+        decl.add_method(selector("description"), describe as extern fn(id, SEL) -> id).unwrap();
+        decl.add_protocol("NSCopying").unwrap();
+        let cls = decl.register();
+
+        assert!(classes().contains(&cls));
+        assert_eq!(name(cls).as_slice(), "RustIntrospectionTest");
+        assert!(superclass(cls) == Some(nsobject));
+        assert!(superclass(nsobject) == None);
+        assert!(superclasses(cls) == vec![nsobject]);
+        assert!(metaclass(cls) != cls);
+        assert!(instance_size(cls) >= instance_size(nsobject) + 4);
+
+        let mut own: Vec<String> = methods(cls).into_iter().map(|m| m.name).collect();
+        own.sort();
+        assert_eq!(own, vec!["description".to_string(), "zero".to_string()]);
+        let zero = methods(cls).into_iter().find(|m| m.name.as_slice() == "zero").unwrap();
+        assert!(zero.selector == selector("zero"));
+        assert_eq!(zero.encoding.as_slice(), "I@:");
+
+        // The override hides NSObject's own `description`.
+        let all = all_methods(cls);
+        assert_eq!(all.iter().filter(|m| m.name.as_slice() == "description").count(), 1);
+        assert!(all.iter().any(|m| m.name.as_slice() == "init"));
+
+        let ivars = ivars(cls);
+        assert_eq!(ivars.len(), 1);
+        assert_eq!(ivars[0].name.as_slice(), "count");
+        assert_eq!(ivars[0].encoding.as_slice(), "I");
+        assert!(properties(cls).is_empty());
+        assert_eq!(protocols(cls), vec!["NSCopying".to_string()]);
+    }
+}
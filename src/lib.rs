@@ -13,14 +13,47 @@
 #![comment = "The Servo Parallel Browser Project"]
 #![license = "MIT"]
 
-#![feature(globs)]
+#![feature(globs, macro_rules, phase, unboxed_closures)]
 
 #![allow(non_snake_case)]
 
 extern crate libc;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+#[phase(plugin)]
+extern crate serde_macros;
+#[cfg(test)]
+extern crate test;
 
-#[cfg(target_os="macos")]
+#[macro_escape]
+mod macros;
+
+// Off macOS, the modules that talk to the runtime are only built with the `mock-runtime`
+// feature, which replaces the runtime with an in-process stand-in, or the `gnustep` feature,
+// which uses GNUstep's runtime and Foundation. GNUstep does not provide AppKit.
+#[cfg(any(target_os="macos", feature="mock-runtime"))]
 pub mod appkit;
-#[cfg(target_os="macos")]
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod associated;
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
 pub mod base;
-
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod block;
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod declare;
+pub mod encoding;
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod exception;
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod foundation;
+pub mod geometry;
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod introspect;
+#[cfg(feature="mock-runtime")]
+pub mod mock;
+pub mod msgsend;
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod rc;
+#[cfg(any(target_os="macos", feature="mock-runtime", feature="gnustep"))]
+pub mod swizzle;
@@ -0,0 +1,140 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// Looks up a class by name, as in `class!(NSString)`. The class is cached at the call site once
+/// it has been found.
+#[macro_export]
+macro_rules! class(
+    ($name:ident) => ({
+        static CACHED: $crate::base::CachedClass = $crate::base::CachedClass {
+            ptr: ::std::sync::atomic::INIT_ATOMIC_UINT,
+        };
+        CACHED.get(stringify!($name))
+    })
+)
+
+/// Registers a selector whose name is spelled out as in Objective-C source, e.g. `sel!(alloc)` or
+/// `sel!(initWithTitle:action:keyEquivalent:)`. The selector string is built at compile time and
+/// the selector is cached at the call site after its first use.
+#[macro_export]
+macro_rules! sel(
+    ($name:ident) => ({
+        static CACHED: $crate::base::CachedSelector = $crate::base::CachedSelector {
+            ptr: ::std::sync::atomic::INIT_ATOMIC_UINT,
+        };
+        CACHED.get(stringify!($name))
+    });
+    ($($name:ident :)+) => ({
+        static CACHED: $crate::base::CachedSelector = $crate::base::CachedSelector {
+            ptr: ::std::sync::atomic::INIT_ATOMIC_UINT,
+        };
+        CACHED.get(concat!($(stringify!($name), ":"),+))
+    })
+)
+
+/// Initializes a static `AssociationKey`, e.g.
+///
+///     static WINDOW_STATE: AssociationKey<WindowState> = association_key!();
+#[macro_export]
+macro_rules! association_key(
+    () => (
+        $crate::associated::AssociationKey {
+            unique: 0,
+        }
+    )
+)
+
+/// Declares a typed handle to instances of a class, which implements `ObjCObject` and `Encode`
+/// and dereferences to the handle of its superclass:
+///
+///     objc_class!(NSObject)
+///     objc_class!(NSResponder: NSObject)
+///     objc_class!(NSWindow: NSResponder)
+///
+/// The handle's name must be the name of the class.
+#[macro_export]
+macro_rules! objc_class(
+    ($name:ident) => (
+        #[repr(C)]
+        #[deriving(Clone, PartialEq, Show)]
+        pub struct $name {
+            obj: $crate::base::id,
+        }
+
+        impl $name {
+            /// Returns a `nil` handle.
+            #[inline]
+            pub fn nil() -> $name {
+                $name {
+                    obj: $crate::base::nil,
+                }
+            }
+        }
+
+        impl $crate::base::ObjCObject for $name {
+            #[inline]
+            fn class_name(_: Option<$name>) -> &'static str {
+                stringify!($name)
+            }
+
+            #[inline]
+            unsafe fn from_id(obj: $crate::base::id) -> $name {
+                $name {
+                    obj: obj,
+                }
+            }
+
+            #[inline]
+            fn as_id(&self) -> $crate::base::id {
+                self.obj
+            }
+        }
+
+        impl $crate::base::Encode for $name {
+            #[inline]
+            fn encode(_: Option<$name>) -> &'static str {
+                "@"
+            }
+        }
+    );
+    ($name:ident : $superclass:ty) => (
+        objc_class!($name)
+
+        impl Deref<$superclass> for $name {
+            #[inline]
+            fn deref<'a>(&'a self) -> &'a $superclass {
+                // Every handle is a single `id`, so they all share a representation.
+                unsafe {
+                    ::std::mem::transmute(self)
+                }
+            }
+        }
+    )
+)
+
+/// Sends a message using Objective-C syntax, e.g.
+///
+///     let window: id = msg_send![window, initWithContentRect:rect
+///                                        styleMask:style
+///                                        backing:backing
+///                                        defer:false];
+///
+/// The selector is assembled from the keyword parts, so it always has exactly as many colons as
+/// the message has arguments. The result type is inferred as for `ObjCMethodCall::send`.
+#[macro_export]
+macro_rules! msg_send(
+    ($obj:expr, $name:ident) => ({
+        use $crate::base::ObjCMethodCall;
+        ($obj).send(sel!($name), ())
+    });
+    ($obj:expr, $($name:ident : $arg:expr)+) => ({
+        use $crate::base::ObjCMethodCall;
+        ($obj).send(sel!($($name:)+), ($($arg,)+))
+    })
+)
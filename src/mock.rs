@@ -0,0 +1,1257 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An in-process stand-in for the Objective-C runtime, enabled by the `mock-runtime` feature so
+//! that the rest of the crate can be built and tested where there is no real runtime.
+//!
+//! It implements the runtime functions used by `base`, `block`, `declare` and `rc` over a
+//! registry of fake classes, which starts out with a root class `NSObject` and the protocols
+//! `NSObject` and `NSCopying`. Other classes, such as the AppKit classes the `appkit` wrappers
+//! talk to, can be stood in for with `define_class`. Messages that no class implements return
+//! zero, or a zeroed struct or float, instead of raising, so wrappers can be tested by checking
+//! the messages they send with `record`:
+//!
+//!     define_class("NSWindow", "NSResponder");
+//!     let ((), messages) = record(|| window.center());
+//!     assert_eq!(messages, vec![Message { receiver: window.as_id(), selector: "center".to_string() }]);
+//!
+//! `objc_msgSend` cannot be written in Rust, since it must pass on arguments it knows nothing
+//! about. Instead, with this feature, `base` looks up each method's implementation with
+//! `objc_msg_lookup` and calls it directly.
+
+use base::{Class, IMP, Ivar, Method, ObjCMethodCall, Property, Protocol, SEL, id, nil};
+use base::{c_string, objc_super};
+use block::BLOCK_HAS_COPY_DISPOSE;
+use declare::MethodImplementation;
+
+use libc;
+use std::c_str::CString;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, Once, ONCE_INIT};
+
+/// A message sent while recording with `record`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Message {
+    pub receiver: id,
+    pub selector: String,
+}
+
+// Both classes and metaclasses. The isa pointer comes first so that classes are also objects.
+#[repr(C)]
+struct MockClass {
+    isa: *mut MockClass,
+    superclass: *mut MockClass,
+    name: String,
+    c_name: CString,
+    // Boxed so that `class_getInstanceMethod` can hand out stable pointers.
+    methods: HashMap<uint, Box<MockMethod>>,
+    ivars: Vec<Box<MockIvar>>,
+    protocols: Vec<*mut Protocol>,
+    instance_size: uint,
+    registered: bool,
+}
+
+impl MockClass {
+    fn new(name: &str, superclass: *mut MockClass, instance_size: uint) -> MockClass {
+        MockClass {
+            isa: ptr::null_mut(),
+            superclass: superclass,
+            name: name.to_string(),
+            c_name: name.to_c_str(),
+            methods: HashMap::new(),
+            ivars: Vec::new(),
+            protocols: Vec::new(),
+            instance_size: instance_size,
+            registered: false,
+        }
+    }
+}
+
+struct MockMethod {
+    sel: SEL,
+    imp: IMP,
+    types: CString,
+}
+
+struct MockIvar {
+    name: String,
+    c_name: CString,
+    types: CString,
+    offset: uint,
+}
+
+struct MockProtocol {
+    name: CString,
+    protocols: Vec<*mut Protocol>,
+    methods: Vec<MockMethodDescription>,
+    registered: bool,
+}
+
+// Only recorded, since nothing here checks that classes implement their protocols' methods.
+#[allow(dead_code)]
+struct MockMethodDescription {
+    sel: SEL,
+    types: CString,
+    required: bool,
+    instance: bool,
+}
+
+// Instances are zeroed blocks of their class's instance size, starting with the isa pointer.
+// They are never freed, so that their retain counts can still be checked after deallocation.
+#[repr(C)]
+struct MockObject {
+    isa: *mut MockClass,
+}
+
+struct Runtime {
+    classes: HashMap<String, *mut MockClass>,
+    selectors: HashMap<String, SEL>,
+    // Selectors point at their names, which live here.
+    selector_names: Vec<CString>,
+    protocols: HashMap<String, *mut Protocol>,
+    retain_counts: HashMap<uint, uint>,
+    // The objects associated with each object, by key, and whether they were retained.
+    associations: HashMap<uint, HashMap<uint, (id, bool)>>,
+}
+
+impl Runtime {
+    fn new() -> Runtime {
+        let mut runtime = Runtime {
+            classes: HashMap::new(),
+            selectors: HashMap::new(),
+            selector_names: Vec::new(),
+            protocols: HashMap::new(),
+            retain_counts: HashMap::new(),
+            associations: HashMap::new(),
+        };
+        runtime.add_root_class();
+        runtime.add_protocol("NSObject");
+        runtime.add_protocol("NSCopying");
+        runtime
+    }
+
+    fn selector(&mut self, name: &str) -> SEL {
+        match self.selectors.find_equiv(&name) {
+            Some(sel) => return *sel,
+            None => {}
+        }
+        let c_name = name.to_c_str();
+        let sel = unsafe {
+            SEL::from_ptr(c_name.as_ptr() as *const libc::c_void)
+        };
+        self.selector_names.push(c_name);
+        self.selectors.insert(name.to_string(), sel);
+        sel
+    }
+
+    /// Returns null if the name is taken.
+    fn allocate_class(&mut self, superclass: *mut MockClass, name: &str) -> *mut MockClass {
+        if self.classes.find_equiv(&name).is_some() {
+            return ptr::null_mut()
+        }
+        unsafe {
+            let (instance_size, meta_superclass) = if superclass.is_null() {
+                (mem::size_of::<MockObject>(), ptr::null_mut())
+            } else {
+                ((*superclass).instance_size, (*superclass).isa)
+            };
+            let metaclass: *mut MockClass =
+                mem::transmute(box MockClass::new(name, meta_superclass, mem::size_of::<MockClass>()));
+            let cls: *mut MockClass = mem::transmute(box MockClass::new(name, superclass, instance_size));
+            (*cls).isa = metaclass;
+            if superclass.is_null() {
+                // As in the real runtime, the root metaclass is its own class and inherits from
+                // the root class, so classes respond to the root class's instance methods.
+                (*metaclass).isa = metaclass;
+                (*metaclass).superclass = cls;
+            } else {
+                (*metaclass).isa = (*(*superclass).isa).isa;
+            }
+            self.classes.insert(name.to_string(), cls);
+            cls
+        }
+    }
+
+    fn add_method<F:MethodImplementation>(&mut self, cls: *mut MockClass, name: &str, imp: F) {
+        let sel = self.selector(name);
+        let types = imp.encoding();
+        unsafe {
+            insert_method(cls, sel, imp.imp(), types.as_slice().to_c_str());
+        }
+    }
+
+    fn add_root_class(&mut self) {
+        let cls = self.allocate_class(ptr::null_mut(), "NSObject");
+        let metaclass = unsafe { (*cls).isa };
+        self.add_method(metaclass, "alloc", nsobject_alloc as extern fn(id, SEL) -> id);
+        self.add_method(metaclass, "new", nsobject_new as extern fn(id, SEL) -> id);
+        self.add_method(metaclass, "class", nsobject_self as extern fn(id, SEL) -> id);
+        self.add_method(cls, "init", nsobject_self as extern fn(id, SEL) -> id);
+        self.add_method(cls, "class", nsobject_class as extern fn(id, SEL) -> Class);
+        self.add_method(cls, "retain", nsobject_retain as extern fn(id, SEL) -> id);
+        self.add_method(cls, "release", nsobject_release as extern fn(id, SEL));
+        self.add_method(cls, "autorelease", nsobject_autorelease as extern fn(id, SEL) -> id);
+        self.add_method(cls, "dealloc", nsobject_dealloc as extern fn(id, SEL));
+        self.add_method(cls, "isKindOfClass:",
+                        nsobject_is_kind_of_class as extern fn(id, SEL, Class) -> bool);
+        self.add_method(cls, "respondsToSelector:",
+                        nsobject_responds_to_selector as extern fn(id, SEL, SEL) -> bool);
+        self.add_method(cls, "conformsToProtocol:",
+                        nsobject_conforms_to_protocol as extern fn(id, SEL, id) -> bool);
+        unsafe {
+            (*cls).registered = true;
+        }
+    }
+
+    /// Returns null if the name is taken.
+    fn allocate_protocol(&mut self, name: &str) -> *mut Protocol {
+        if self.protocols.find_equiv(&name).is_some() {
+            return ptr::null_mut()
+        }
+        let protocol: *mut Protocol = unsafe {
+            mem::transmute(box MockProtocol {
+                name: name.to_c_str(),
+                protocols: Vec::new(),
+                methods: Vec::new(),
+                registered: false,
+            })
+        };
+        self.protocols.insert(name.to_string(), protocol);
+        protocol
+    }
+
+    fn add_protocol(&mut self, name: &str) {
+        let protocol = self.allocate_protocol(name);
+        unsafe {
+            (*(protocol as *mut MockProtocol)).registered = true;
+        }
+    }
+}
+
+static mut RUNTIME: *mut Mutex<Runtime> = 0 as *mut Mutex<Runtime>;
+static RUNTIME_INIT: Once = ONCE_INIT;
+
+// The lock must not be held while calling method implementations, which may send messages.
+fn runtime() -> MutexGuard<'static, Runtime> {
+    RUNTIME_INIT.doit(|| {
+        unsafe {
+            RUNTIME = mem::transmute(box Mutex::new(Runtime::new()));
+        }
+    });
+    unsafe {
+        (*RUNTIME).lock()
+    }
+}
+
+unsafe fn insert_method(cls: *mut MockClass, sel: SEL, imp: IMP, types: CString) -> bool {
+    let key = sel.as_ptr() as uint;
+    if (*cls).methods.contains_key(&key) {
+        return false
+    }
+    (*cls).methods.insert(key, box MockMethod {
+        sel: sel,
+        imp: imp,
+        types: types,
+    });
+    true
+}
+
+// Callers must hold the runtime lock.
+unsafe fn find_method(cls: *mut MockClass, sel: SEL) -> Option<*const MockMethod> {
+    let key = sel.as_ptr() as uint;
+    let mut cls = cls;
+    while !cls.is_null() {
+        match (*cls).methods.find(&key) {
+            Some(method) => return Some(&**method as *const MockMethod),
+            None => {}
+        }
+        cls = (*cls).superclass;
+    }
+    None
+}
+
+/// Declares and registers a subclass of `superclass` with no methods of its own, or returns the
+/// class named `name` if there already is one.
+pub fn define_class(name: &str, superclass: &str) -> Class {
+    let mut runtime = runtime();
+    match runtime.classes.find_equiv(&name) {
+        Some(cls) => return *cls as Class,
+        None => {}
+    }
+    let superclass = match runtime.classes.find_equiv(&superclass) {
+        Some(cls) => *cls,
+        None => fail!("no superclass named {} to define {} with", superclass, name),
+    };
+    let cls = runtime.allocate_class(superclass, name);
+    unsafe {
+        (*cls).registered = true;
+    }
+    cls as Class
+}
+
+/// A method for `stub_class` to add, made with `stub`.
+pub struct Stub {
+    selector: &'static str,
+    imp: IMP,
+    types: String,
+}
+
+/// Makes a stub of the method named `selector`, which is a class method if the name starts with
+/// `+`, as in `+mainScreen`.
+pub fn stub<F:MethodImplementation>(selector: &'static str, imp: F) -> Stub {
+    Stub {
+        selector: selector,
+        types: imp.encoding(),
+        imp: imp.imp(),
+    }
+}
+
+/// Defines the class named `name` as `define_class` does and adds the `methods` it does not have
+/// yet, so that tests can stand in for the framework's classes without clashing.
+pub fn stub_class(name: &str, superclass: &str, methods: &[Stub]) -> Class {
+    let cls = define_class(name, superclass);
+    for method in methods.iter() {
+        let (target, selector) = if method.selector.starts_with("+") {
+            (object_getClass(cls), method.selector.slice_from(1))
+        } else {
+            (cls, method.selector)
+        };
+        unsafe {
+            class_addMethod(target,
+                            sel_registerName(selector.to_c_str().as_ptr()),
+                            method.imp,
+                            method.types.to_c_str().as_ptr());
+        }
+    }
+    cls
+}
+
+/// Returns an object's retain count, which is zero once it has been deallocated.
+pub fn retain_count(obj: id) -> uint {
+    match runtime().retain_counts.find(&(obj as uint)) {
+        Some(count) => *count,
+        None => 0,
+    }
+}
+
+local_data_key!(RECORDED: RefCell<Vec<Message>>)
+
+/// Runs `f`, and returns its result along with the messages this task sent while it ran.
+pub fn record<T>(f: || -> T) -> (T, Vec<Message>) {
+    let previous = RECORDED.replace(Some(RefCell::new(Vec::new())));
+    let result = f();
+    let recorded = RECORDED.replace(previous).unwrap();
+    let messages = recorded.borrow().clone();
+    (result, messages)
+}
+
+fn record_message(receiver: id, sel: SEL) {
+    match RECORDED.get() {
+        Some(recorded) => {
+            recorded.borrow_mut().push(Message {
+                receiver: receiver,
+                selector: unsafe { c_string(sel_getName(sel)) },
+            });
+        }
+        None => {}
+    }
+}
+
+local_data_key!(THROWN: id)
+
+/// Stands in for `@throw` in method implementations, taking ownership of `exception`. The mock
+/// runtime cannot unwind, so instead of raising, `exception` is returned by the innermost
+/// `catch_exception` once its closure returns.
+pub fn throw_exception(exception: id) {
+    THROWN.replace(Some(exception));
+}
+
+// Returns the exception thrown with `throw_exception` since the last call, or `nil`.
+#[doc(hidden)]
+pub fn take_exception() -> id {
+    match THROWN.replace(None) {
+        Some(exception) => exception,
+        None => nil,
+    }
+}
+
+// Stands in for every method that is not implemented, including all messages to nil. It only
+// returns an `id`, so `base` calls a function returning a zeroed result of the right type instead
+// when `objc_msg_lookup` returns it.
+extern fn nil_imp(_: id, _: SEL) -> id {
+    nil
+}
+
+/// Returns true if `imp` is the stand-in `objc_msg_lookup` returns for unimplemented methods.
+pub fn is_unimplemented(imp: IMP) -> bool {
+    imp as *const u8 == nil_imp as *const u8
+}
+
+extern fn nsobject_alloc(cls: id, _: SEL) -> id {
+    let mut runtime = runtime();
+    unsafe {
+        let cls = cls as *mut MockClass;
+        let obj = libc::calloc(1, (*cls).instance_size as libc::size_t) as *mut MockObject;
+        (*obj).isa = cls;
+        runtime.retain_counts.insert(obj as uint, 1);
+        obj as id
+    }
+}
+
+extern fn nsobject_new(cls: id, _: SEL) -> id {
+    unsafe {
+        let obj: id = cls.send("alloc", ());
+        obj.send("init", ())
+    }
+}
+
+extern fn nsobject_self(this: id, _: SEL) -> id {
+    this
+}
+
+extern fn nsobject_class(this: id, _: SEL) -> Class {
+    object_getClass(this)
+}
+
+extern fn nsobject_retain(this: id, _: SEL) -> id {
+    objc_retain(this)
+}
+
+extern fn nsobject_release(this: id, _: SEL) {
+    objc_release(this)
+}
+
+extern fn nsobject_autorelease(this: id, _: SEL) -> id {
+    objc_autorelease(this)
+}
+
+extern fn nsobject_dealloc(this: id, _: SEL) {
+    unsafe {
+        objc_removeAssociatedObjects(this);
+    }
+}
+
+extern fn nsobject_is_kind_of_class(this: id, _: SEL, cls: Class) -> bool {
+    let _runtime = runtime();
+    let mut current = object_getClass(this) as *mut MockClass;
+    while !current.is_null() {
+        if current as Class == cls {
+            return true
+        }
+        current = unsafe { (*current).superclass };
+    }
+    false
+}
+
+extern fn nsobject_responds_to_selector(this: id, _: SEL, sel: SEL) -> bool {
+    let _runtime = runtime();
+    unsafe {
+        find_method(object_getClass(this) as *mut MockClass, sel).is_some()
+    }
+}
+
+extern fn nsobject_conforms_to_protocol(this: id, _: SEL, protocol: id) -> bool {
+    let _runtime = runtime();
+    let mut cls = object_getClass(this) as *mut MockClass;
+    while !cls.is_null() {
+        unsafe {
+            if class_adopts(cls, protocol as *mut Protocol) {
+                return true
+            }
+            cls = (*cls).superclass;
+        }
+    }
+    false
+}
+
+// Callers must hold the runtime lock.
+unsafe fn class_adopts(cls: *mut MockClass, protocol: *mut Protocol) -> bool {
+    (*cls).protocols.iter().any(|p| incorporates(*p, protocol))
+}
+
+// Whether `protocol` is `other` or incorporates it. Callers must hold the runtime lock.
+unsafe fn incorporates(protocol: *mut Protocol, other: *mut Protocol) -> bool {
+    protocol == other ||
+        (*(protocol as *mut MockProtocol)).protocols.iter().any(|p| incorporates(*p, other))
+}
+
+#[no_mangle]
+pub extern "C" fn sel_registerName(name: *const libc::c_char) -> SEL {
+    let name = unsafe { c_string(name) };
+    runtime().selector(name.as_slice())
+}
+
+#[no_mangle]
+pub extern "C" fn sel_getName(sel: SEL) -> *const libc::c_char {
+    sel.as_ptr() as *const libc::c_char
+}
+
+#[no_mangle]
+pub extern "C" fn objc_getClass(name: *const libc::c_char) -> id {
+    let name = unsafe { c_string(name) };
+    let runtime = runtime();
+    match runtime.classes.find_equiv(&name.as_slice()) {
+        Some(cls) if unsafe { (**cls).registered } => *cls as id,
+        _ => nil,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_getName(protocol: *mut Protocol) -> *const libc::c_char {
+    (*(protocol as *const MockProtocol)).name.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn property_getName(_: *mut Property) -> *const libc::c_char {
+    fail!("the mock runtime has no properties")
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn property_getAttributes(_: *mut Property) -> *const libc::c_char {
+    fail!("the mock runtime has no properties")
+}
+
+#[no_mangle]
+pub extern "C" fn objc_getProtocol(name: *const libc::c_char) -> *mut Protocol {
+    let name = unsafe { c_string(name) };
+    match runtime().protocols.find_equiv(&name.as_slice()) {
+        Some(protocol) if unsafe { (*(*protocol as *mut MockProtocol)).registered } => *protocol,
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn objc_allocateProtocol(name: *const libc::c_char) -> *mut Protocol {
+    let name = unsafe { c_string(name) };
+    runtime().allocate_protocol(name.as_slice())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_registerProtocol(protocol: *mut Protocol) {
+    let _runtime = runtime();
+    (*(protocol as *mut MockProtocol)).registered = true;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_addMethodDescription(protocol: *mut Protocol,
+                                                       name: SEL,
+                                                       types: *const libc::c_char,
+                                                       required: bool,
+                                                       instance: bool) {
+    let _runtime = runtime();
+    (*(protocol as *mut MockProtocol)).methods.push(MockMethodDescription {
+        sel: name,
+        types: c_string(types).as_slice().to_c_str(),
+        required: required,
+        instance: instance,
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_addProtocol(protocol: *mut Protocol, addition: *mut Protocol) {
+    let _runtime = runtime();
+    (*(protocol as *mut MockProtocol)).protocols.push(addition);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn protocol_conformsToProtocol(protocol: *mut Protocol,
+                                                     other: *mut Protocol)
+                                                     -> bool {
+    let _runtime = runtime();
+    incorporates(protocol, other)
+}
+
+#[no_mangle]
+pub extern "C" fn objc_allocateClassPair(superclass: Class,
+                                         name: *const libc::c_char,
+                                         _: libc::size_t)
+                                         -> Class {
+    let name = unsafe { c_string(name) };
+    runtime().allocate_class(superclass as *mut MockClass, name.as_slice()) as Class
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_registerClassPair(cls: Class) {
+    let _runtime = runtime();
+    (*(cls as *mut MockClass)).registered = true;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_disposeClassPair(cls: Class) {
+    let mut runtime = runtime();
+    let cls: Box<MockClass> = mem::transmute(cls);
+    let metaclass: Box<MockClass> = mem::transmute(cls.isa);
+    runtime.classes.remove(&cls.name);
+    drop(metaclass);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_addMethod(cls: Class,
+                                         name: SEL,
+                                         imp: IMP,
+                                         types: *const libc::c_char)
+                                         -> bool {
+    let _runtime = runtime();
+    insert_method(cls as *mut MockClass, name, imp, c_string(types).as_slice().to_c_str())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_replaceMethod(cls: Class,
+                                             name: SEL,
+                                             imp: IMP,
+                                             types: *const libc::c_char)
+                                             -> Option<IMP> {
+    let _runtime = runtime();
+    let cls = cls as *mut MockClass;
+    match (*cls).methods.find_mut(&(name.as_ptr() as uint)) {
+        Some(method) => {
+            let previous = method.imp;
+            method.imp = imp;
+            return Some(previous)
+        }
+        None => {}
+    }
+    insert_method(cls, name, imp, c_string(types).as_slice().to_c_str());
+    None
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_addIvar(cls: Class,
+                                       name: *const libc::c_char,
+                                       size: libc::size_t,
+                                       alignment: u8,
+                                       types: *const libc::c_char)
+                                       -> bool {
+    let _runtime = runtime();
+    let cls = cls as *mut MockClass;
+    let name = c_string(name);
+    if (*cls).registered || (*cls).ivars.iter().any(|ivar| ivar.name == name) {
+        return false
+    }
+    // The alignment is given as its base two logarithm.
+    let align = 1u << alignment as uint;
+    let offset = ((*cls).instance_size + align - 1) & !(align - 1);
+    (*cls).instance_size = offset + size as uint;
+    (*cls).ivars.push(box MockIvar {
+        c_name: name.as_slice().to_c_str(),
+        name: name,
+        types: c_string(types).as_slice().to_c_str(),
+        offset: offset,
+    });
+    true
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_addProtocol(cls: Class, protocol: *mut Protocol) -> bool {
+    let _runtime = runtime();
+    let cls = cls as *mut MockClass;
+    if (*cls).protocols.contains(&protocol) {
+        return false
+    }
+    (*cls).protocols.push(protocol);
+    true
+}
+
+// Copies `items` into a list allocated with `malloc`, for the caller to free.
+unsafe fn copy_list<T:Clone>(items: &[T], count: *mut libc::c_uint) -> *mut T {
+    if !count.is_null() {
+        *count = items.len() as libc::c_uint;
+    }
+    if items.is_empty() {
+        return ptr::null_mut()
+    }
+    let list = libc::malloc((items.len() * mem::size_of::<T>()) as libc::size_t) as *mut T;
+    for (i, item) in items.iter().enumerate() {
+        ptr::write(list.offset(i as int), item.clone());
+    }
+    list
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_copyClassList(count: *mut libc::c_uint) -> *mut Class {
+    let runtime = runtime();
+    let classes: Vec<Class> = runtime.classes.values()
+                                             .filter(|cls| (***cls).registered)
+                                             .map(|cls| *cls as Class)
+                                             .collect();
+    copy_list(classes.as_slice(), count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_copyMethodList(cls: Class,
+                                              count: *mut libc::c_uint)
+                                              -> *mut *mut Method {
+    let _runtime = runtime();
+    let methods: Vec<*mut Method> = (*(cls as *mut MockClass)).methods.values().map(|method| {
+        &**method as *const MockMethod as *mut Method
+    }).collect();
+    copy_list(methods.as_slice(), count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_copyIvarList(cls: Class, count: *mut libc::c_uint) -> *mut Ivar {
+    let _runtime = runtime();
+    let ivars: Vec<Ivar> = (*(cls as *mut MockClass)).ivars.iter().map(|ivar| {
+        &**ivar as *const MockIvar as Ivar
+    }).collect();
+    copy_list(ivars.as_slice(), count)
+}
+
+// Classes here never have properties.
+#[no_mangle]
+pub unsafe extern "C" fn class_copyPropertyList(_: Class,
+                                                count: *mut libc::c_uint)
+                                                -> *mut *mut Property {
+    copy_list(&[], count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_copyProtocolList(cls: Class,
+                                                count: *mut libc::c_uint)
+                                                -> *mut *mut Protocol {
+    let _runtime = runtime();
+    copy_list((*(cls as *mut MockClass)).protocols.as_slice(), count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_conformsToProtocol(cls: Class, protocol: *mut Protocol) -> bool {
+    let _runtime = runtime();
+    class_adopts(cls as *mut MockClass, protocol)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_getInstanceSize(cls: Class) -> libc::size_t {
+    let _runtime = runtime();
+    (*(cls as *mut MockClass)).instance_size as libc::size_t
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_getInstanceVariable(cls: Class, name: *const libc::c_char) -> Ivar {
+    let name = c_string(name);
+    let _runtime = runtime();
+    let mut cls = cls as *mut MockClass;
+    while !cls.is_null() {
+        for ivar in (*cls).ivars.iter() {
+            if ivar.name == name {
+                return &**ivar as *const MockIvar as Ivar
+            }
+        }
+        cls = (*cls).superclass;
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_getMethodImplementation(cls: Class, name: SEL) -> IMP {
+    let _runtime = runtime();
+    match find_method(cls as *mut MockClass, name) {
+        Some(method) => (*method).imp,
+        None => nil_imp as IMP,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_getInstanceMethod(cls: Class, name: SEL) -> *mut Method {
+    let _runtime = runtime();
+    match find_method(cls as *mut MockClass, name) {
+        Some(method) => method as *mut Method,
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_getName(cls: Class) -> *const libc::c_char {
+    (*(cls as *mut MockClass)).c_name.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn class_getSuperclass(cls: Class) -> Class {
+    let _runtime = runtime();
+    (*(cls as *mut MockClass)).superclass as Class
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ivar_getName(ivar: Ivar) -> *const libc::c_char {
+    (*(ivar as *const MockIvar)).c_name.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ivar_getOffset(ivar: Ivar) -> libc::ptrdiff_t {
+    (*(ivar as *const MockIvar)).offset as libc::ptrdiff_t
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ivar_getTypeEncoding(ivar: Ivar) -> *const libc::c_char {
+    (*(ivar as *const MockIvar)).types.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn method_exchangeImplementations(m1: *mut Method, m2: *mut Method) {
+    let _runtime = runtime();
+    let (m1, m2) = (m1 as *mut MockMethod, m2 as *mut MockMethod);
+    let imp = (*m1).imp;
+    (*m1).imp = (*m2).imp;
+    (*m2).imp = imp;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn method_getImplementation(method: *mut Method) -> IMP {
+    let _runtime = runtime();
+    (*(method as *const MockMethod)).imp
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn method_setImplementation(method: *mut Method, imp: IMP) -> IMP {
+    let _runtime = runtime();
+    let method = method as *mut MockMethod;
+    let previous = (*method).imp;
+    (*method).imp = imp;
+    previous
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn method_getName(method: *mut Method) -> SEL {
+    (*(method as *const MockMethod)).sel
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn method_getTypeEncoding(method: *mut Method) -> *const libc::c_char {
+    (*(method as *const MockMethod)).types.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn object_setInstanceVariable(obj: id,
+                                                    name: *const libc::c_char,
+                                                    value: *mut libc::c_void) {
+    let ivar = class_getInstanceVariable(object_getClass(obj), name);
+    if ivar != 0 {
+        let offset = ivar_getOffset(ivar) as int;
+        *((obj as *mut u8).offset(offset) as *mut *mut libc::c_void) = value;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn object_getInstanceVariable(obj: id,
+                                                    name: *const libc::c_char,
+                                                    out_value: *mut *mut libc::c_void) {
+    let ivar = class_getInstanceVariable(object_getClass(obj), name);
+    if ivar != 0 {
+        let offset = ivar_getOffset(ivar) as int;
+        *out_value = *((obj as *mut u8).offset(offset) as *mut *mut libc::c_void);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn object_getClass(obj: id) -> Class {
+    if obj == nil {
+        return nil
+    }
+    unsafe {
+        (*(obj as *mut MockObject)).isa as Class
+    }
+}
+
+/// Returns the implementation of the method `op` for `receiver`, which must be called with the
+/// receiver and selector followed by the message's arguments.
+#[no_mangle]
+pub extern "C" fn objc_msg_lookup(receiver: id, op: SEL) -> IMP {
+    record_message(receiver, op);
+    if receiver == nil {
+        return nil_imp as IMP
+    }
+    let _runtime = runtime();
+    unsafe {
+        match find_method(object_getClass(receiver) as *mut MockClass, op) {
+            Some(method) => (*method).imp,
+            None => nil_imp as IMP,
+        }
+    }
+}
+
+/// Returns the implementation `super_class` has for `op`, which must be called with the receiver
+/// in `sup`.
+#[no_mangle]
+pub extern "C" fn objc_msg_lookup_super(sup: *const objc_super, op: SEL) -> IMP {
+    unsafe {
+        record_message((*sup).receiver, op);
+        if (*sup).receiver == nil {
+            return nil_imp as IMP
+        }
+        let _runtime = runtime();
+        match find_method((*sup).super_class as *mut MockClass, op) {
+            Some(method) => (*method).imp,
+            None => nil_imp as IMP,
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn objc_retain(obj: id) -> id {
+    if obj != nil {
+        match runtime().retain_counts.find_mut(&(obj as uint)) {
+            Some(count) => *count += 1,
+            // Classes are never deallocated.
+            None => {}
+        }
+    }
+    obj
+}
+
+#[no_mangle]
+pub extern "C" fn objc_release(obj: id) {
+    if obj == nil {
+        return
+    }
+    let deallocate = match runtime().retain_counts.find_mut(&(obj as uint)) {
+        Some(count) if *count > 0 => {
+            *count -= 1;
+            *count == 0
+        }
+        _ => false,
+    };
+    if deallocate {
+        let sel = runtime().selector("dealloc");
+        objc_msg_lookup(obj, sel)(obj, sel);
+    }
+}
+
+// Each task has a stack of autorelease pools, which are identified by their depth.
+local_data_key!(POOLS: RefCell<Vec<Vec<id>>>)
+
+fn with_pools<T>(f: |&mut Vec<Vec<id>>| -> T) -> T {
+    if POOLS.get().is_none() {
+        POOLS.replace(Some(RefCell::new(Vec::new())));
+    }
+    let pools = POOLS.get().unwrap();
+    let mut pools = pools.borrow_mut();
+    f(&mut *pools)
+}
+
+#[no_mangle]
+pub extern "C" fn objc_autorelease(obj: id) -> id {
+    if obj != nil {
+        with_pools(|pools| {
+            match pools.last_mut() {
+                Some(pool) => pool.push(obj),
+                // Like the real runtime, leak objects autoreleased with no pool in place.
+                None => {}
+            }
+        });
+    }
+    obj
+}
+
+#[no_mangle]
+pub extern "C" fn objc_autoreleasePoolPush() -> *mut libc::c_void {
+    with_pools(|pools| {
+        pools.push(Vec::new());
+        pools.len() as *mut libc::c_void
+    })
+}
+
+/// Pops the pool `context` and any pools pushed after it, releasing their objects.
+#[no_mangle]
+pub extern "C" fn objc_autoreleasePoolPop(context: *mut libc::c_void) {
+    let depth = context as uint;
+    let objects = with_pools(|pools| {
+        let mut objects = Vec::new();
+        while pools.len() >= depth {
+            objects.push_all(pools.pop().unwrap().as_slice());
+        }
+        objects
+    });
+    for obj in objects.iter() {
+        objc_release(*obj);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_initWeak(location: *mut id, obj: id) -> id {
+    *location = obj;
+    obj
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_storeWeak(location: *mut id, obj: id) -> id {
+    *location = obj;
+    obj
+}
+
+// Weak references are not cleared on deallocation; loading one checks the retain count instead.
+#[no_mangle]
+pub unsafe extern "C" fn objc_loadWeakRetained(location: *mut id) -> id {
+    let obj = *location;
+    let alive = obj != nil && match runtime().retain_counts.find(&(obj as uint)) {
+        Some(count) => *count > 0,
+        None => true,
+    };
+    if alive {
+        objc_retain(obj)
+    } else {
+        nil
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_copyWeak(to: *mut id, from: *mut id) {
+    *to = *from;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_destroyWeak(location: *mut id) {
+    *location = nil;
+}
+
+// Nothing can catch an exception here, so raising one aborts like an uncaught exception.
+#[no_mangle]
+pub extern "C" fn objc_exception_throw(_: id) -> ! {
+    // Unwinding out of an `extern "C"` function is undefined behavior, so this cannot fail.
+    let _ = writeln!(&mut io::stderr(),
+                     "an Objective-C exception was raised with the mock runtime");
+    unsafe {
+        libc::abort()
+    }
+}
+
+/// Stands in for the Foundation constant, which is `nil` here.
+#[no_mangle]
+pub static NSDefaultRunLoopMode: id = 0 as id;
+
+static OBJC_ASSOCIATION_ASSIGN: libc::uintptr_t = 0;
+
+// Copying policies retain instead, since objects here cannot be copied.
+#[no_mangle]
+pub unsafe extern "C" fn objc_setAssociatedObject(object: id,
+                                                  key: *const libc::c_void,
+                                                  value: id,
+                                                  policy: libc::uintptr_t) {
+    let retain = value != nil && policy != OBJC_ASSOCIATION_ASSIGN;
+    if retain {
+        objc_retain(value);
+    }
+    let previous = {
+        let mut runtime = runtime();
+        let associations = runtime.associations.find_or_insert_with(object as uint,
+                                                                    |_| HashMap::new());
+        if value == nil {
+            associations.pop(&(key as uint))
+        } else {
+            associations.swap(key as uint, (value, retain))
+        }
+    };
+    // Releasing may deallocate, so the runtime must not be locked.
+    match previous {
+        Some((previous, true)) => objc_release(previous),
+        _ => {}
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_getAssociatedObject(object: id, key: *const libc::c_void) -> id {
+    match runtime().associations.find(&(object as uint)) {
+        Some(associations) => match associations.find(&(key as uint)) {
+            Some(&(value, _)) => value,
+            None => nil,
+        },
+        None => nil,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn objc_removeAssociatedObjects(object: id) {
+    let associations = runtime().associations.pop(&(object as uint));
+    match associations {
+        Some(associations) => {
+            for &(value, retained) in associations.values() {
+                if retained {
+                    objc_release(value);
+                }
+            }
+        }
+        None => {}
+    }
+}
+
+// The start of every block, as laid out by the block ABI.
+#[repr(C)]
+struct MockBlock {
+    isa: *const libc::c_void,
+    flags: libc::c_int,
+    reserved: libc::c_int,
+    invoke: *const libc::c_void,
+    descriptor: *const MockBlockDescriptor,
+}
+
+#[repr(C)]
+struct MockBlockDescriptor {
+    reserved: libc::c_ulong,
+    size: libc::c_ulong,
+    // Only present with `BLOCK_HAS_COPY_DISPOSE`.
+    copy_helper: extern "C" fn(*mut libc::c_void, *const libc::c_void),
+    dispose_helper: extern "C" fn(*mut libc::c_void),
+}
+
+static BLOCK_REFCOUNT_MASK: libc::c_int = 0xfffe;
+static BLOCK_NEEDS_FREE: libc::c_int = 1 << 24;
+
+/// The class of blocks on the stack.
+#[no_mangle]
+pub static _NSConcreteStackBlock: *const libc::c_void = 0 as *const libc::c_void;
+
+/// The class of blocks copied to the heap.
+#[no_mangle]
+pub static _NSConcreteMallocBlock: *const libc::c_void = 0 as *const libc::c_void;
+
+/// Copies a stack block to the heap, or retains a heap block. As in the real runtime, the
+/// reference count is kept in the flags, in steps of two.
+#[no_mangle]
+pub unsafe extern "C" fn _Block_copy(block: *const libc::c_void) -> *mut libc::c_void {
+    let block = block as *mut MockBlock;
+    if block.is_null() {
+        return ptr::null_mut()
+    }
+    if (*block).flags & BLOCK_NEEDS_FREE != 0 {
+        (*block).flags += 2;
+        return block as *mut libc::c_void
+    }
+    let descriptor = (*block).descriptor;
+    let size = (*descriptor).size as uint;
+    let copy = libc::malloc(size as libc::size_t) as *mut MockBlock;
+    ptr::copy_nonoverlapping_memory(copy as *mut u8, block as *const u8, size);
+    (*copy).isa = &_NSConcreteMallocBlock as *const *const libc::c_void as *const libc::c_void;
+    (*copy).flags = ((*copy).flags & !BLOCK_REFCOUNT_MASK) | BLOCK_NEEDS_FREE | 2;
+    if (*copy).flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        ((*descriptor).copy_helper)(copy as *mut libc::c_void, block as *const libc::c_void);
+    }
+    copy as *mut libc::c_void
+}
+
+/// Releases a heap block, disposing of it once it is no longer referenced.
+#[no_mangle]
+pub unsafe extern "C" fn _Block_release(block: *const libc::c_void) {
+    let block = block as *mut MockBlock;
+    if block.is_null() || (*block).flags & BLOCK_NEEDS_FREE == 0 {
+        return
+    }
+    (*block).flags -= 2;
+    if (*block).flags & BLOCK_REFCOUNT_MASK == 0 {
+        if (*block).flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+            ((*(*block).descriptor).dispose_helper)(block as *mut libc::c_void);
+        }
+        libc::free(block as *mut libc::c_void);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use appkit::{NSPoint, NSRect, NSSize, NSString, NSView, NSWindow};
+    use base::{ObjCMethodCall, ObjCObject, class, class_getSuperclass, id, nil, selector};
+    use super::*;
+
+    fn message(receiver: id, selector: &str) -> Message {
+        Message {
+            receiver: receiver,
+            selector: selector.to_string(),
+        }
+    }
+
+    #[test]
+    pub fn test_classes() {
+        let cls = define_class("MockTestWidget", "NSObject");
+        assert!(define_class("MockTestWidget", "NSObject") == cls);
+        assert!(class("MockTestWidget") == cls);
+        assert!(class("MockTestMissing") == nil);
+        unsafe {
+            assert!(class_getSuperclass(cls) == class("NSObject"));
+            let is_kind: bool = cls.send("isKindOfClass:", (class("NSObject"),));
+            assert!(is_kind);
+        }
+    }
+
+    #[test]
+    pub fn test_send_and_record() {
+        let cls = define_class("MockTestRecorder", "NSObject");
+        unsafe {
+            let (obj, messages) = record(|| {
+                let obj: id = cls.send("new", ());
+                let result: uint = obj.send("frobnicate:", (3u,));
+                assert_eq!(result, 0);
+                obj
+            });
+            assert_eq!(messages, vec![message(cls, "new"),
+                                      message(cls, "alloc"),
+                                      message(obj, "init"),
+                                      message(obj, "frobnicate:")]);
+            let responds: bool = obj.send("respondsToSelector:", (selector("init"),));
+            assert!(responds);
+            let responds: bool = obj.send("respondsToSelector:", (selector("frobnicate:"),));
+            assert!(!responds);
+        }
+    }
+
+    #[test]
+    pub fn test_zeroed_results() {
+        let cls = define_class("MockTestUnimplemented", "NSObject");
+        let zero = NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(0.0, 0.0));
+        unsafe {
+            let obj: id = cls.send("new", ());
+            for receiver in [obj, nil].iter() {
+                let frame: NSRect = (*receiver).send("frame", ());
+                assert_eq!(frame, zero);
+                let scale: f64 = (*receiver).send("backingScaleFactor", ());
+                assert_eq!(scale, 0.0);
+                let count: uint = (*receiver).send("count", ());
+                assert_eq!(count, 0);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_retain_counts() {
+        unsafe {
+            let obj: id = class("NSObject").send("new", ());
+            assert_eq!(retain_count(obj), 1);
+            let _: id = obj.send("retain", ());
+            assert_eq!(retain_count(obj), 2);
+            let ((), messages) = record(|| {
+                let _: () = obj.send("release", ());
+                let _: () = obj.send("release", ());
+            });
+            assert_eq!(retain_count(obj), 0);
+            assert_eq!(messages, vec![message(obj, "release"),
+                                      message(obj, "release"),
+                                      message(obj, "dealloc")]);
+        }
+    }
+
+    #[test]
+    pub fn test_appkit_wrappers() {
+        define_class("NSResponder", "NSObject");
+        define_class("NSWindow", "NSResponder");
+        define_class("NSView", "NSResponder");
+        define_class("NSString", "NSObject");
+
+        unsafe {
+            let window = NSWindow::alloc();
+            let title = NSString::alloc();
+            let ((), messages) = record(|| {
+                window.setTitle_(title);
+                window.center();
+            });
+            assert_eq!(messages, vec![message(window.as_id(), "setTitle:"),
+                                      message(window.as_id(), "center")]);
+            assert!(window.downcast::<NSWindow>() == Some(window));
+            assert!(window.downcast::<NSView>().is_none());
+        }
+    }
+}
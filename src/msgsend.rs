@@ -0,0 +1,277 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Selection of the Objective-C messenger entry point for a given return type.
+//!
+//! `objc_msgSend` and friends have no fixed signature: callers are expected to cast them to a
+//! function pointer type matching the method being called. Which of the entry points must be
+//! used depends on how the target ABI returns the method's result, so that decision lives here
+//! and the callers in `base` simply transmute the result of `messenger` to the right type.
+
+/// The entry points of the Objective-C messenger.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Messenger {
+    /// `objc_msgSend`, for results returned in registers.
+    MsgSend,
+    /// `objc_msgSend_stret`, for structures returned through a hidden pointer argument.
+    MsgSendStret,
+    /// `objc_msgSend_fpret`, for floating point results returned on the x87 stack.
+    MsgSendFpret,
+    /// `objc_msgSendSuper`, which takes a pointer to an `objc_super` in place of the receiver.
+    MsgSendSuper,
+    /// `objc_msgSendSuper_stret`.
+    MsgSendSuperStret,
+}
+
+/// The architectures the Objective-C runtime supports.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Arch {
+    X86,
+    X86_64,
+    Arm,
+    AArch64,
+}
+
+#[cfg(target_arch = "x86")]
+pub static TARGET_ARCH: Arch = X86;
+#[cfg(target_arch = "x86_64")]
+pub static TARGET_ARCH: Arch = X86_64;
+#[cfg(target_arch = "arm")]
+pub static TARGET_ARCH: Arch = Arm;
+#[cfg(target_arch = "aarch64")]
+pub static TARGET_ARCH: Arch = AArch64;
+
+/// What a method returns, as far as the choice of messenger is concerned.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ReturnKind {
+    /// Nothing, an integer, a pointer or an object.
+    ReturnsScalar,
+    /// A `float` or a `double`.
+    ReturnsFloat,
+    /// A structure of the given size in bytes.
+    ReturnsStruct(uint),
+}
+
+/// Returns the messenger that must be used to call a method returning `kind` on `arch`.
+pub fn messenger_for(arch: Arch, kind: ReturnKind) -> Messenger {
+    match (arch, kind) {
+        // i386 returns floating point values in st(0), and only returns structures in
+        // registers when they fit in `eax:edx` and have a power-of-two size.
+        (X86, ReturnsFloat) => MsgSendFpret,
+        (X86, ReturnsStruct(1)) | (X86, ReturnsStruct(2)) |
+        (X86, ReturnsStruct(4)) | (X86, ReturnsStruct(8)) => MsgSend,
+        (X86, ReturnsStruct(_)) => MsgSendStret,
+
+        // x86_64 returns structures of up to two eightbytes in registers. `objc_msgSend_fpret`
+        // is only needed for `long double`, which Rust has no equivalent of.
+        (X86_64, ReturnsStruct(size)) if size > 16 => MsgSendStret,
+
+        // 32-bit ARM returns anything larger than a word in memory.
+        (Arm, ReturnsStruct(size)) if size > 4 => MsgSendStret,
+
+        // AArch64 has neither `objc_msgSend_stret` nor `objc_msgSend_fpret`.
+        _ => MsgSend,
+    }
+}
+
+/// Returns the messenger that must be used to send a message to the superclass for a method
+/// returning `kind` on `arch`. There is no `fpret` variant, since the superclass messengers
+/// leave the result wherever the method put it.
+pub fn super_messenger_for(arch: Arch, kind: ReturnKind) -> Messenger {
+    match messenger_for(arch, kind) {
+        MsgSendStret => MsgSendSuperStret,
+        _ => MsgSendSuper,
+    }
+}
+
+#[cfg(any(target_os = "macos", all(test, not(feature = "gnustep"))))]
+extern {
+    fn objc_msgSend();
+    fn objc_msgSendSuper();
+}
+
+#[cfg(all(any(target_os = "macos", all(test, not(feature = "gnustep"))),
+          not(target_arch = "aarch64")))]
+extern {
+    fn objc_msgSend_stret();
+    fn objc_msgSend_fpret();
+    fn objc_msgSendSuper_stret();
+}
+
+/// Returns the entry point of the given messenger. The result must be transmuted to a function
+/// pointer whose signature matches the method being called, with the receiver and selector as
+/// the first two arguments.
+#[cfg(all(any(target_os = "macos", all(test, not(feature = "gnustep"))),
+          not(target_arch = "aarch64")))]
+pub fn messenger(messenger: Messenger) -> unsafe extern "C" fn() {
+    match messenger {
+        MsgSend => objc_msgSend,
+        MsgSendStret => objc_msgSend_stret,
+        MsgSendFpret => objc_msgSend_fpret,
+        MsgSendSuper => objc_msgSendSuper,
+        MsgSendSuperStret => objc_msgSendSuper_stret,
+    }
+}
+
+#[cfg(all(any(target_os = "macos", all(test, not(feature = "gnustep"))), target_arch = "aarch64"))]
+pub fn messenger(messenger: Messenger) -> unsafe extern "C" fn() {
+    match messenger {
+        MsgSend => objc_msgSend,
+        MsgSendSuper => objc_msgSendSuper,
+        _ => fail!("{} does not exist on aarch64", messenger),
+    }
+}
+
+/// Returns the messenger entry point to use for a method returning `kind` on the current target.
+#[cfg(any(target_os = "macos", all(test, not(feature = "gnustep"))))]
+#[inline]
+pub fn messenger_for_return(kind: ReturnKind) -> unsafe extern "C" fn() {
+    messenger(messenger_for(TARGET_ARCH, kind))
+}
+
+/// Returns the superclass messenger entry point to use for a method returning `kind` on the
+/// current target.
+#[cfg(any(target_os = "macos", all(test, not(feature = "gnustep"))))]
+#[inline]
+pub fn super_messenger_for_return(kind: ReturnKind) -> unsafe extern "C" fn() {
+    messenger(super_messenger_for(TARGET_ARCH, kind))
+}
+
+#[cfg(test)]
+mod test {
+    use libc;
+    use std::mem;
+    use super::*;
+
+    #[test]
+    pub fn test_scalars_use_msg_send() {
+        for arch in [X86, X86_64, Arm, AArch64].iter() {
+            assert_eq!(messenger_for(*arch, ReturnsScalar), MsgSend);
+        }
+    }
+
+    #[test]
+    pub fn test_floats() {
+        assert_eq!(messenger_for(X86, ReturnsFloat), MsgSendFpret);
+        assert_eq!(messenger_for(X86_64, ReturnsFloat), MsgSend);
+        assert_eq!(messenger_for(Arm, ReturnsFloat), MsgSend);
+        assert_eq!(messenger_for(AArch64, ReturnsFloat), MsgSend);
+    }
+
+    #[test]
+    pub fn test_structs() {
+        // NSPoint and NSRect with 64-bit fields.
+        assert_eq!(messenger_for(X86, ReturnsStruct(8)), MsgSend);
+        assert_eq!(messenger_for(X86, ReturnsStruct(16)), MsgSendStret);
+        assert_eq!(messenger_for(X86_64, ReturnsStruct(16)), MsgSend);
+        assert_eq!(messenger_for(X86_64, ReturnsStruct(32)), MsgSendStret);
+        assert_eq!(messenger_for(Arm, ReturnsStruct(4)), MsgSend);
+        assert_eq!(messenger_for(Arm, ReturnsStruct(8)), MsgSendStret);
+        assert_eq!(messenger_for(AArch64, ReturnsStruct(32)), MsgSend);
+    }
+
+    #[test]
+    pub fn test_super() {
+        assert_eq!(super_messenger_for(X86, ReturnsFloat), MsgSendSuper);
+        assert_eq!(super_messenger_for(X86, ReturnsStruct(16)), MsgSendSuperStret);
+        assert_eq!(super_messenger_for(X86_64, ReturnsStruct(16)), MsgSendSuper);
+        assert_eq!(super_messenger_for(X86_64, ReturnsStruct(32)), MsgSendSuperStret);
+        assert_eq!(super_messenger_for(AArch64, ReturnsStruct(32)), MsgSendSuper);
+    }
+
+    // Stand-ins for the runtime's messengers so that the transmutes can be exercised off macOS.
+    // Each one reports which entry point was called through its result. They are left out with
+    // `gnustep`, since they would replace the messengers libobjc2 exports.
+
+    #[cfg(all(not(target_os = "macos"), not(target_arch = "aarch64"), not(feature = "gnustep")))]
+    #[repr(C)]
+    struct Quad {
+        a: u64,
+        b: u64,
+        c: u64,
+        d: u64,
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(feature = "gnustep")))]
+    #[no_mangle]
+    pub extern "C" fn objc_msgSend(receiver: libc::intptr_t, _: libc::intptr_t) -> libc::intptr_t {
+        receiver + 1
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(target_arch = "aarch64"), not(feature = "gnustep")))]
+    #[no_mangle]
+    pub extern "C" fn objc_msgSend_stret(receiver: libc::intptr_t, _: libc::intptr_t) -> Quad {
+        let r = receiver as u64;
+        Quad { a: r, b: r + 1, c: r + 2, d: r + 3 }
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(target_arch = "aarch64"), not(feature = "gnustep")))]
+    #[no_mangle]
+    pub extern "C" fn objc_msgSend_fpret(_: libc::intptr_t, _: libc::intptr_t) -> f64 {
+        0.5
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(feature = "gnustep")))]
+    #[no_mangle]
+    pub extern "C" fn objc_msgSendSuper(sup: libc::intptr_t, _: libc::intptr_t) -> libc::intptr_t {
+        sup + 2
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(target_arch = "aarch64"), not(feature = "gnustep")))]
+    #[no_mangle]
+    pub extern "C" fn objc_msgSendSuper_stret(sup: libc::intptr_t, _: libc::intptr_t) -> Quad {
+        let s = sup as u64;
+        Quad { a: s, b: s + 2, c: s + 4, d: s + 6 }
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(feature = "gnustep")))]
+    #[test]
+    pub fn test_scalar_transmute() {
+        unsafe {
+            let f: unsafe extern "C" fn(libc::intptr_t, libc::intptr_t) -> libc::intptr_t =
+                mem::transmute(messenger_for_return(ReturnsScalar));
+            assert_eq!(f(41, 0), 42);
+        }
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(target_arch = "aarch64"), not(feature = "gnustep")))]
+    #[test]
+    pub fn test_struct_transmute() {
+        unsafe {
+            let f: unsafe extern "C" fn(libc::intptr_t, libc::intptr_t) -> Quad =
+                mem::transmute(messenger_for_return(ReturnsStruct(mem::size_of::<Quad>())));
+            let quad = f(10, 0);
+            assert_eq!((quad.a, quad.b, quad.c, quad.d), (10, 11, 12, 13));
+        }
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(target_arch = "aarch64"), not(feature = "gnustep")))]
+    #[test]
+    pub fn test_super_transmute() {
+        unsafe {
+            let f: unsafe extern "C" fn(libc::intptr_t, libc::intptr_t) -> libc::intptr_t =
+                mem::transmute(super_messenger_for_return(ReturnsScalar));
+            assert_eq!(f(40, 0), 42);
+            let f: unsafe extern "C" fn(libc::intptr_t, libc::intptr_t) -> Quad =
+                mem::transmute(super_messenger_for_return(ReturnsStruct(mem::size_of::<Quad>())));
+            let quad = f(10, 0);
+            assert_eq!((quad.a, quad.b, quad.c, quad.d), (10, 12, 14, 16));
+        }
+    }
+
+    #[cfg(all(not(target_os = "macos"), target_arch = "x86", not(feature = "gnustep")))]
+    #[test]
+    pub fn test_float_transmute() {
+        unsafe {
+            let f: unsafe extern "C" fn(libc::intptr_t, libc::intptr_t) -> f64 =
+                mem::transmute(messenger_for_return(ReturnsFloat));
+            assert_eq!(f(0, 0), 0.5);
+        }
+    }
+}
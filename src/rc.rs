@@ -0,0 +1,481 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Smart pointers that manage the reference counts of Objective-C objects.
+//!
+//! Following Cocoa's conventions, objects returned by methods whose names begin with `alloc`,
+//! `new`, `copy` or `mutableCopy` are already owned by the caller and should be wrapped with
+//! `StrongPtr::new`; any other object the caller wants to keep must be retained with
+//! `StrongPtr::retain`. `Id<T>` does the same for objects behind a typed handle `T`, and derefs
+//! to the handle. Objects that are only needed briefly can instead be left autoreleased inside an
+//! `autoreleasepool` scope.
+
+use base::{id, nil};
+use base::{objc_autorelease, objc_autoreleasePoolPop, objc_autoreleasePoolPush};
+use base::{objc_copyWeak, objc_destroyWeak, objc_initWeak, objc_loadWeakRetained};
+use base::{objc_release, objc_retain, objc_storeWeak};
+
+use libc;
+use std::kinds::marker::CovariantType;
+use std::mem;
+
+/// An owning reference to an object, which is retained when cloned and released when dropped.
+pub struct StrongPtr {
+    ptr: id,
+}
+
+impl StrongPtr {
+    /// Takes ownership of an object the caller already owns, such as the result of `alloc`,
+    /// `new` or `copy`, without retaining it.
+    #[inline]
+    pub unsafe fn new(ptr: id) -> StrongPtr {
+        StrongPtr {
+            ptr: ptr,
+        }
+    }
+
+    /// Retains an object the caller does not own, such as an autoreleased return value.
+    #[inline]
+    pub unsafe fn retain(ptr: id) -> StrongPtr {
+        StrongPtr {
+            ptr: objc_retain(ptr),
+        }
+    }
+
+    /// Hands the reference over to the current autorelease pool and returns the raw object.
+    #[inline]
+    pub fn autorelease(self) -> id {
+        let ptr = self.ptr;
+        unsafe {
+            mem::forget(self);
+            objc_autorelease(ptr)
+        }
+    }
+
+    /// Gives up ownership of the object without releasing it, returning it at +1.
+    #[inline]
+    pub fn into_raw(self) -> id {
+        let ptr = self.ptr;
+        unsafe {
+            mem::forget(self);
+        }
+        ptr
+    }
+
+    /// Returns a weak reference to the object.
+    #[inline]
+    pub fn weak(&self) -> WeakPtr {
+        unsafe {
+            WeakPtr::new(self.ptr)
+        }
+    }
+
+    #[inline]
+    pub fn is_nil(&self) -> bool {
+        self.ptr == nil
+    }
+}
+
+impl Deref<id> for StrongPtr {
+    #[inline]
+    fn deref<'a>(&'a self) -> &'a id {
+        &self.ptr
+    }
+}
+
+impl Clone for StrongPtr {
+    #[inline]
+    fn clone(&self) -> StrongPtr {
+        unsafe {
+            StrongPtr::retain(self.ptr)
+        }
+    }
+}
+
+impl Drop for StrongPtr {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            objc_release(self.ptr);
+        }
+    }
+}
+
+/// An owning reference to an object that derefs to the handle type `T`, e.g. `Id<NSWindow>`.
+/// Like `StrongPtr`, it is retained when cloned and released when dropped.
+///
+/// `T` must be `id` or a handle holding nothing but an `id`, so that the object can be viewed
+/// as a `T`.
+pub struct Id<T> {
+    strong: StrongPtr,
+    marker: CovariantType<T>,
+}
+
+impl<T> Id<T> {
+    /// Takes ownership of an object the caller already owns, without retaining it.
+    #[inline]
+    pub unsafe fn new(ptr: id) -> Id<T> {
+        Id {
+            strong: StrongPtr::new(ptr),
+            marker: CovariantType,
+        }
+    }
+
+    /// Retains an object the caller does not own.
+    #[inline]
+    pub unsafe fn retain(ptr: id) -> Id<T> {
+        Id {
+            strong: StrongPtr::retain(ptr),
+            marker: CovariantType,
+        }
+    }
+
+    /// Hands the reference over to the current autorelease pool and returns the handle.
+    #[inline]
+    pub fn autorelease(self) -> T {
+        unsafe {
+            mem::transmute_copy(&self.strong.autorelease())
+        }
+    }
+
+    /// Gives up ownership of the object without releasing it, returning it at +1.
+    #[inline]
+    pub fn into_raw(self) -> T {
+        unsafe {
+            mem::transmute_copy(&self.strong.into_raw())
+        }
+    }
+
+    /// Converts the reference into an untyped one.
+    #[inline]
+    pub fn into_strong(self) -> StrongPtr {
+        self.strong
+    }
+
+    /// Returns a weak reference to the object.
+    #[inline]
+    pub fn weak(&self) -> WeakPtr {
+        self.strong.weak()
+    }
+}
+
+impl<T> Deref<T> for Id<T> {
+    #[inline]
+    fn deref<'a>(&'a self) -> &'a T {
+        unsafe {
+            &*(&self.strong.ptr as *const id as *const T)
+        }
+    }
+}
+
+impl<T> Clone for Id<T> {
+    #[inline]
+    fn clone(&self) -> Id<T> {
+        Id {
+            strong: self.strong.clone(),
+            marker: CovariantType,
+        }
+    }
+}
+
+/// A weak reference to an object, which does not keep it alive and is cleared by the runtime when
+/// the object is deallocated.
+pub struct WeakPtr {
+    // The runtime keeps track of the location of every weak reference, so it must not move.
+    location: Box<id>,
+}
+
+impl WeakPtr {
+    /// Creates a weak reference to `obj`, which may be `nil`.
+    pub unsafe fn new(obj: id) -> WeakPtr {
+        let mut location = box nil;
+        objc_initWeak(&mut *location, obj);
+        WeakPtr {
+            location: location,
+        }
+    }
+
+    /// Returns a strong reference to the object, or `None` if it has been deallocated.
+    pub fn load(&self) -> Option<StrongPtr> {
+        unsafe {
+            let obj = objc_loadWeakRetained(self.location_ptr());
+            if obj == nil {
+                None
+            } else {
+                Some(StrongPtr::new(obj))
+            }
+        }
+    }
+
+    /// Makes this weak reference point to `obj` instead.
+    pub unsafe fn store(&mut self, obj: id) {
+        objc_storeWeak(self.location_ptr(), obj);
+    }
+
+    #[inline]
+    fn location_ptr(&self) -> *mut id {
+        &*self.location as *const id as *mut id
+    }
+}
+
+impl Clone for WeakPtr {
+    fn clone(&self) -> WeakPtr {
+        let location = box nil;
+        let weak = WeakPtr {
+            location: location,
+        };
+        unsafe {
+            objc_copyWeak(weak.location_ptr(), self.location_ptr());
+        }
+        weak
+    }
+}
+
+impl Drop for WeakPtr {
+    fn drop(&mut self) {
+        unsafe {
+            objc_destroyWeak(self.location_ptr());
+        }
+    }
+}
+
+/// Runs `f` inside a new autorelease pool, which is drained when `f` returns or unwinds:
+///
+///     autoreleasepool(|pool| {
+///         let title = NSString::alloc().init_str("Title\0");
+///         let title = pool.autorelease(StrongPtr::new(title.as_id()));
+///         window.setTitle_(ObjCObject::from_id(*title));
+///     });
+///
+/// Values tied to the pool with `autoreleased` or `autorelease` cannot escape `f`; to keep one,
+/// retain it with `Autoreleased::retain`.
+pub fn autoreleasepool<T>(f: |&AutoreleasePool| -> T) -> T {
+    let depth = pool_depth() + 1;
+    POOL_DEPTH.replace(Some(depth));
+    let pool = AutoreleasePool {
+        context: unsafe { objc_autoreleasePoolPush() },
+        depth: depth,
+    };
+    f(&pool)
+}
+
+// How many `autoreleasepool` calls are active on this task.
+local_data_key!(POOL_DEPTH: uint)
+
+fn pool_depth() -> uint {
+    match POOL_DEPTH.get() {
+        Some(depth) => *depth,
+        None => 0,
+    }
+}
+
+/// An autorelease pool that is active for the duration of an `autoreleasepool` call.
+pub struct AutoreleasePool {
+    context: *mut libc::c_void,
+    depth: uint,
+}
+
+impl AutoreleasePool {
+    /// Ties an object that has been autoreleased into this pool, such as the result of a method
+    /// that returns an object the caller does not own, to the lifetime of the pool.
+    #[inline]
+    pub unsafe fn autoreleased<'p>(&'p self, obj: id) -> Autoreleased<'p> {
+        Autoreleased {
+            ptr: obj,
+            pool: self,
+        }
+    }
+
+    /// Gives up ownership of `obj` to this pool.
+    ///
+    /// The runtime autoreleases into the innermost pool, so this fails if another
+    /// `autoreleasepool` is active inside this one: the object would be released when that pool
+    /// drains, while the result still borrowed this one.
+    pub fn autorelease<'p>(&'p self, obj: StrongPtr) -> Autoreleased<'p> {
+        if pool_depth() != self.depth {
+            fail!("autorelease called on a pool that is not the innermost one")
+        }
+        unsafe {
+            self.autoreleased(obj.autorelease())
+        }
+    }
+}
+
+impl Drop for AutoreleasePool {
+    fn drop(&mut self) {
+        unsafe {
+            objc_autoreleasePoolPop(self.context);
+        }
+        POOL_DEPTH.replace(Some(self.depth - 1));
+    }
+}
+
+/// An object that is kept alive by an autorelease pool, and so cannot outlive it.
+pub struct Autoreleased<'p> {
+    ptr: id,
+    pool: &'p AutoreleasePool,
+}
+
+impl<'p> Autoreleased<'p> {
+    /// Retains the object so that it can outlive the pool.
+    #[inline]
+    pub fn retain(&self) -> StrongPtr {
+        unsafe {
+            StrongPtr::retain(self.ptr)
+        }
+    }
+}
+
+impl<'p> Deref<id> for Autoreleased<'p> {
+    #[inline]
+    fn deref<'a>(&'a self) -> &'a id {
+        &self.ptr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::{id, nil};
+    use super::*;
+
+    #[cfg(feature = "mock-runtime")]
+    fn new_object() -> id {
+        use base::{ObjCMethodCall, class};
+        unsafe {
+            class("NSObject").send("new", ())
+        }
+    }
+
+    #[cfg(feature = "mock-runtime")]
+    #[test]
+    pub fn test_strong() {
+        use mock::retain_count;
+
+        let obj = new_object();
+        unsafe {
+            let strong = StrongPtr::new(obj);
+            assert_eq!(retain_count(obj), 1);
+            {
+                let other = strong.clone();
+                assert!(*other == obj);
+                assert_eq!(retain_count(obj), 2);
+            }
+            assert_eq!(retain_count(obj), 1);
+        }
+        assert_eq!(retain_count(obj), 0);
+    }
+
+    #[cfg(feature = "mock-runtime")]
+    #[test]
+    pub fn test_typed() {
+        use mock::retain_count;
+
+        let obj = new_object();
+        unsafe {
+            let typed: Id<id> = Id::new(obj);
+            assert!(*typed == obj);
+            {
+                let other = typed.clone();
+                assert!(*other == obj);
+                assert_eq!(retain_count(obj), 2);
+            }
+            assert_eq!(retain_count(obj), 1);
+            let strong = typed.into_strong();
+            assert!(*strong == obj);
+        }
+        assert_eq!(retain_count(obj), 0);
+    }
+
+    #[cfg(feature = "mock-runtime")]
+    #[test]
+    pub fn test_retain_autoreleased() {
+        use mock::retain_count;
+
+        // An autoreleased return value is owned by the pool, not by us.
+        let obj = new_object();
+        autoreleasepool(|_| {
+            unsafe {
+                let strong = StrongPtr::retain(obj);
+                assert_eq!(retain_count(obj), 2);
+                let returned = strong.autorelease();
+                assert!(returned == obj);
+                assert_eq!(retain_count(obj), 2);
+                let raw = StrongPtr::new(obj).into_raw();
+                assert!(raw == obj);
+                assert_eq!(retain_count(obj), 2);
+            }
+        });
+        assert_eq!(retain_count(obj), 1);
+    }
+
+    #[cfg(feature = "mock-runtime")]
+    #[test]
+    pub fn test_weak() {
+        use mock::retain_count;
+
+        let obj = new_object();
+        let strong = unsafe { StrongPtr::new(obj) };
+        let weak = strong.weak();
+        let copy = weak.clone();
+        {
+            let loaded = weak.load().unwrap();
+            assert!(*loaded == obj);
+            assert_eq!(retain_count(obj), 2);
+        }
+        drop(strong);
+        assert!(weak.load().is_none());
+        assert!(copy.load().is_none());
+    }
+
+    #[cfg(feature = "mock-runtime")]
+    #[test]
+    pub fn test_autoreleasepool() {
+        use mock::retain_count;
+
+        let obj = new_object();
+        let kept = autoreleasepool(|_| {
+            let kept = autoreleasepool(|inner| {
+                let autoreleased = inner.autorelease(unsafe { StrongPtr::new(obj) });
+                assert!(*autoreleased == obj);
+                let kept = autoreleased.retain();
+                assert_eq!(retain_count(obj), 2);
+                kept
+            });
+            // Draining the inner pool released the autoreleased reference.
+            assert_eq!(retain_count(obj), 1);
+            kept
+        });
+        assert!(*kept == obj);
+        assert_eq!(retain_count(obj), 1);
+        drop(kept);
+        assert_eq!(retain_count(obj), 0);
+    }
+
+    #[cfg(feature = "mock-runtime")]
+    #[test]
+    #[should_fail]
+    pub fn test_autorelease_outer_pool() {
+        autoreleasepool(|outer| {
+            autoreleasepool(|_| {
+                outer.autorelease(unsafe { StrongPtr::new(new_object()) });
+            });
+        });
+    }
+
+    #[test]
+    pub fn test_nil() {
+        unsafe {
+            let strong = StrongPtr::retain(nil);
+            assert!(strong.is_nil());
+            let weak = WeakPtr::new(nil);
+            assert!(weak.load().is_none());
+            let _: id = strong.clone().autorelease();
+        }
+    }
+}
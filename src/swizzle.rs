@@ -0,0 +1,216 @@
+// Copyright 2013 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Replacing the implementations of existing methods, e.g. to observe every event a window
+//! receives:
+//!
+//!     static mut ORIGINAL_SEND_EVENT: Option<extern fn(id, SEL, id)> = None;
+//!
+//!     extern fn send_event(this: id, sel: SEL, event: id) {
+//!         println!("{} received {}", this, event);
+//!         unsafe {
+//!             ORIGINAL_SEND_EVENT.unwrap()(this, sel, event)
+//!         }
+//!     }
+//!
+//!     ORIGINAL_SEND_EVENT = Some(replace_method(class("NSWindow"), selector("sendEvent:"),
+//!                                               send_event as extern fn(id, SEL, id)).unwrap());
+//!
+//! The replacement can be called as soon as it is installed, possibly on another thread, so it
+//! must cope with the original implementation not having been stored yet.
+
+use base::{Class, Method, SEL, c_string};
+use base::{class_getInstanceMethod, class_getName, class_replaceMethod};
+use base::{method_exchangeImplementations, method_getImplementation, method_getTypeEncoding};
+use base::{method_setImplementation, sel_getName};
+use declare::MethodImplementation;
+use encoding::MethodEncoding;
+
+/// The ways replacing a method can fail.
+#[deriving(Clone, PartialEq, Show)]
+pub enum SwizzleError {
+    /// The class, named first, neither implements nor inherits the method named second.
+    NoSuchMethod(String, String),
+    /// The replacement's signature does not match the method's. Holds the name of the selector,
+    /// the method's type encoding and the replacement's.
+    SignatureMismatch(String, String, String),
+}
+
+unsafe fn find_method(cls: Class, sel: SEL) -> Result<*mut Method, SwizzleError> {
+    let method = class_getInstanceMethod(cls, sel);
+    if method.is_null() {
+        Err(NoSuchMethod(c_string(class_getName(cls)), c_string(sel_getName(sel))))
+    } else {
+        Ok(method)
+    }
+}
+
+// Checks that an implementation with the type encoding `types` can stand in for `method`.
+unsafe fn check_signature(method: *mut Method, sel: SEL, types: &str)
+                          -> Result<(), SwizzleError> {
+    let expected = c_string(method_getTypeEncoding(method));
+    let actual = MethodEncoding::parse(types);
+    let matches = match (actual, MethodEncoding::parse(expected.as_slice())) {
+        (Ok(actual), Ok(expected)) => actual.is_compatible_with(&expected),
+        _ => false,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(SignatureMismatch(c_string(sel_getName(sel)), expected, types.to_string()))
+    }
+}
+
+/// Replaces the instance method `sel` of `cls` with `imp`, returning the implementation it had,
+/// which may have been inherited. Superclasses are not affected. The class methods can be
+/// replaced by passing the metaclass.
+pub unsafe fn replace_method<F:MethodImplementation>(cls: Class, sel: SEL, imp: F)
+                                                     -> Result<F, SwizzleError> {
+    let method = try!(find_method(cls, sel));
+    let types = imp.encoding();
+    try!(check_signature(method, sel, types.as_slice()));
+    // The runtime returns the implementation it replaced, unless the method was inherited and has
+    // just been added to `cls`, in which case `method` is still the superclass's.
+    let original = class_replaceMethod(cls, sel, imp.imp(), types.to_c_str().as_ptr());
+    let original = original.unwrap_or_else(|| method_getImplementation(method));
+    Ok(MethodImplementation::from_imp(original))
+}
+
+/// Sets the implementation of the method that instances of `cls` use for `sel`, returning the
+/// implementation it had. Unlike `replace_method`, this changes the method where it is defined,
+/// so an inherited method is replaced for the superclass that defines it and all its subclasses.
+pub unsafe fn set_implementation<F:MethodImplementation>(cls: Class, sel: SEL, imp: F)
+                                                         -> Result<F, SwizzleError> {
+    let method = try!(find_method(cls, sel));
+    try!(check_signature(method, sel, imp.encoding().as_slice()));
+    Ok(MethodImplementation::from_imp(method_setImplementation(method, imp.imp())))
+}
+
+/// Swaps the implementations of the instance methods `first` and `second` of `cls`, which must
+/// have matching signatures. As with `set_implementation`, inherited methods are swapped where
+/// they are defined.
+pub unsafe fn exchange_implementations(cls: Class, first: SEL, second: SEL)
+                                       -> Result<(), SwizzleError> {
+    let first_method = try!(find_method(cls, first));
+    let second_method = try!(find_method(cls, second));
+    // Each implementation ends up standing in for the other, and compatibility isn't symmetric.
+    let second_types = c_string(method_getTypeEncoding(second_method));
+    try!(check_signature(first_method, first, second_types.as_slice()));
+    let first_types = c_string(method_getTypeEncoding(first_method));
+    try!(check_signature(second_method, second, first_types.as_slice()));
+    method_exchangeImplementations(first_method, second_method);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use base::{ObjCMethodCall, SEL, class, id, selector};
+    use declare::ClassDecl;
+    use super::*;
+
+    extern fn double(_: id, _: SEL, x: u32) -> u32 {
+        x * 2
+    }
+
+    extern fn triple(_: id, _: SEL, x: u32) -> u32 {
+        x * 3
+    }
+
+    static mut ORIGINAL: Option<extern fn(id, SEL, u32) -> u32> = None;
+
+    extern fn double_plus_one(this: id, sel: SEL, x: u32) -> u32 {
+        unsafe {
+            ORIGINAL.unwrap()(this, sel, x) + 1
+        }
+    }
+
+    extern fn wrong_signature(_: id, _: SEL, _: f64) -> u32 {
+        0
+    }
+
+    fn declare_classes(base: &str, sub: &str) -> (id, id) {
+        let mut decl = ClassDecl::new(base, class("NSObject")).unwrap();
+        decl.add_method(selector("compute:"), double as extern fn(id, SEL, u32) -> u32).unwrap();
+        decl.add_method(selector("other:"), triple as extern fn(id, SEL, u32) -> u32).unwrap();
+        let base_cls = decl.register();
+        let sub_cls = ClassDecl::new(sub, base_cls).unwrap().register();
+        unsafe {
+            (base_cls.send("new", ()), sub_cls.send("new", ()))
+        }
+    }
+
+    fn compute(obj: id, sel: &str) -> u32 {
+        unsafe {
+            obj.send(sel, (5u32,))
+        }
+    }
+
+    #[test]
+    pub fn test_replace_method() {
+        let (base, sub) = declare_classes("RustSwizzleTestBase", "RustSwizzleTestSub");
+        let sel = selector("compute:");
+        unsafe {
+            assert_eq!(replace_method(class("RustSwizzleTestSub"), sel,
+                                      wrong_signature as extern fn(id, SEL, f64) -> u32).err(),
+                       Some(SignatureMismatch("compute:".to_string(),
+                                              "I@:I".to_string(),
+                                              "I@:d".to_string())));
+            let missing = replace_method(class("RustSwizzleTestSub"), selector("missing:"),
+                                         triple as extern fn(id, SEL, u32) -> u32);
+            assert_eq!(missing.err(), Some(NoSuchMethod("RustSwizzleTestSub".to_string(),
+                                                        "missing:".to_string())));
+
+            let original = replace_method(class("RustSwizzleTestSub"), sel,
+                                          double_plus_one as extern fn(id, SEL, u32) -> u32);
+            ORIGINAL = Some(original.unwrap());
+        }
+        assert_eq!(compute(sub, "compute:"), 11);
+        // The superclass keeps its own implementation.
+        assert_eq!(compute(base, "compute:"), 10);
+    }
+
+    #[test]
+    pub fn test_set_and_exchange() {
+        let (base, sub) = declare_classes("RustSwizzleTestBase2", "RustSwizzleTestSub2");
+        unsafe {
+            let original = set_implementation(class("RustSwizzleTestSub2"), selector("compute:"),
+                                              triple as extern fn(id, SEL, u32) -> u32).unwrap();
+            assert_eq!(original(base, selector("compute:"), 1), 2);
+        }
+        // The method was inherited, so the superclass changed too.
+        assert_eq!(compute(base, "compute:"), 15);
+        assert_eq!(compute(sub, "compute:"), 15);
+
+        unsafe {
+            set_implementation(class("RustSwizzleTestBase2"), selector("compute:"),
+                               double as extern fn(id, SEL, u32) -> u32).unwrap();
+            exchange_implementations(class("RustSwizzleTestBase2"),
+                                     selector("compute:"),
+                                     selector("other:")).unwrap();
+        }
+        assert_eq!(compute(base, "compute:"), 15);
+        assert_eq!(compute(base, "other:"), 10);
+
+        let mut decl = ClassDecl::new("RustSwizzleTestMismatched", class("NSObject")).unwrap();
+        decl.add_method(selector("compute:"), double as extern fn(id, SEL, u32) -> u32).unwrap();
+        decl.add_method(selector("wrong:"),
+                        wrong_signature as extern fn(id, SEL, f64) -> u32).unwrap();
+        let cls = decl.register();
+        unsafe {
+            assert_eq!(exchange_implementations(cls, selector("compute:"), selector("wrong:")),
+                       Err(SignatureMismatch("compute:".to_string(),
+                                             "I@:I".to_string(),
+                                             "I@:d".to_string())));
+            assert_eq!(exchange_implementations(cls, selector("wrong:"), selector("compute:")),
+                       Err(SignatureMismatch("wrong:".to_string(),
+                                             "I@:d".to_string(),
+                                             "I@:I".to_string())));
+        }
+    }
+}